@@ -0,0 +1,449 @@
+//! Compose key sequences, loaded from the X11 `Compose` file format.
+
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+/// Latin-1 X11 keysym names, starting at `U+00A0`
+const LATIN1: [&str; 96] = [
+    "nobreakspace",
+    "exclamdown",
+    "cent",
+    "sterling",
+    "currency",
+    "yen",
+    "brokenbar",
+    "section",
+    "diaeresis",
+    "copyright",
+    "ordfeminine",
+    "guillemotleft",
+    "notsign",
+    "hyphen",
+    "registered",
+    "macron",
+    "degree",
+    "plusminus",
+    "twosuperior",
+    "threesuperior",
+    "acute",
+    "mu",
+    "paragraph",
+    "periodcentered",
+    "cedilla",
+    "onesuperior",
+    "masculine",
+    "guillemotright",
+    "onequarter",
+    "onehalf",
+    "threequarters",
+    "questiondown",
+    "Agrave",
+    "Aacute",
+    "Acircumflex",
+    "Atilde",
+    "Adiaeresis",
+    "Aring",
+    "AE",
+    "Ccedilla",
+    "Egrave",
+    "Eacute",
+    "Ecircumflex",
+    "Ediaeresis",
+    "Igrave",
+    "Iacute",
+    "Icircumflex",
+    "Idiaeresis",
+    "ETH",
+    "Ntilde",
+    "Ograve",
+    "Oacute",
+    "Ocircumflex",
+    "Otilde",
+    "Odiaeresis",
+    "multiply",
+    "Oslash",
+    "Ugrave",
+    "Uacute",
+    "Ucircumflex",
+    "Udiaeresis",
+    "Yacute",
+    "THORN",
+    "ssharp",
+    "agrave",
+    "aacute",
+    "acircumflex",
+    "atilde",
+    "adiaeresis",
+    "aring",
+    "ae",
+    "ccedilla",
+    "egrave",
+    "eacute",
+    "ecircumflex",
+    "ediaeresis",
+    "igrave",
+    "iacute",
+    "icircumflex",
+    "idiaeresis",
+    "eth",
+    "ntilde",
+    "ograve",
+    "oacute",
+    "ocircumflex",
+    "otilde",
+    "odiaeresis",
+    "division",
+    "oslash",
+    "ugrave",
+    "uacute",
+    "ucircumflex",
+    "udiaeresis",
+    "yacute",
+    "thorn",
+    "ydiaeresis",
+];
+
+/// ASCII punctuation X11 keysym names
+const ASCII: [(&str, char); 33] = [
+    ("space", ' '),
+    ("exclam", '!'),
+    ("quotedbl", '"'),
+    ("numbersign", '#'),
+    ("dollar", '$'),
+    ("percent", '%'),
+    ("ampersand", '&'),
+    ("apostrophe", '\''),
+    ("parenleft", '('),
+    ("parenright", ')'),
+    ("asterisk", '*'),
+    ("plus", '+'),
+    ("comma", ','),
+    ("minus", '-'),
+    ("period", '.'),
+    ("slash", '/'),
+    ("colon", ':'),
+    ("semicolon", ';'),
+    ("less", '<'),
+    ("equal", '='),
+    ("greater", '>'),
+    ("question", '?'),
+    ("at", '@'),
+    ("bracketleft", '['),
+    ("backslash", '\\'),
+    ("bracketright", ']'),
+    ("asciicircum", '^'),
+    ("underscore", '_'),
+    ("grave", '`'),
+    ("braceleft", '{'),
+    ("bar", '|'),
+    ("braceright", '}'),
+    ("asciitilde", '~'),
+];
+
+/// Kind of error found while parsing a [`ComposeTable`]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ComposeErrorKind {
+    /// Missing `:` between the sequence and its result
+    MissingColon,
+    /// Unterminated `<keysym>`
+    UnterminatedKeysym,
+    /// Missing or unterminated quoted result string, or one with escapes
+    /// that aren't valid UTF-8
+    InvalidResult,
+}
+
+/// Error parsing a [`ComposeTable`] from the X11 `Compose` file format
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ComposeError {
+    /// Line number, starting at 1
+    pub line: usize,
+    /// Column number (in characters), starting at 1
+    pub column: usize,
+    /// What went wrong
+    pub kind: ComposeErrorKind,
+}
+
+impl fmt::Display for ComposeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = match self.kind {
+            ComposeErrorKind::MissingColon => "missing `:`",
+            ComposeErrorKind::UnterminatedKeysym => "unterminated `<keysym>`",
+            ComposeErrorKind::InvalidResult => "invalid result string",
+        };
+        write!(f, "{}:{}: {}", self.line, self.column, message)
+    }
+}
+
+impl std::error::Error for ComposeError {}
+
+/// Table of compose key sequences, each mapping the characters typed after
+/// [`Key::Compose`](crate::Key::Compose) to the text they produce
+///
+/// # X11 Compose Format
+/// Tables can be parsed with [`str::parse()`] from the format of X11
+/// `Compose` files:
+///
+/// ```text
+/// # Comment
+/// <Multi_key> <a> <e> : "æ" ae
+/// <Multi_key> <apostrophe> <E> : "É" Eacute
+/// <Multi_key> <U2190> <U2192> : "↔"
+/// <Multi_key> <quotedbl> <backslash> : "\"\\"
+/// <Multi_key> <x> <e> : "\303\251"
+/// ```
+///
+/// Result strings can contain `\"` and `\\` escapes, and octal (`\101`) or
+/// hexadecimal (`\x41`) escapes for bytes, which must form valid UTF-8.
+///
+/// Only sequences starting with `<Multi_key>` are used; other sequences,
+/// `include` lines and sequences using keysyms that don't type a single
+/// character are skipped.
+#[derive(Clone, Debug, Default)]
+pub struct ComposeTable(BTreeMap<String, String>);
+
+impl ComposeTable {
+    /// Create an empty compose table
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a sequence, replacing any previous result for it
+    pub fn insert(&mut self, sequence: &str, result: &str) {
+        self.0.insert(sequence.to_string(), result.to_string());
+    }
+
+    /// Get the result of a complete sequence
+    pub fn get(&self, sequence: &str) -> Option<&str> {
+        self.0.get(sequence).map(String::as_str)
+    }
+
+    /// Check if a sequence is the start of (or is) a sequence in the table
+    pub fn is_prefix(&self, sequence: &str) -> bool {
+        self.0
+            .range(sequence.to_string()..)
+            .next()
+            .map_or(false, |(s, _)| s.starts_with(sequence))
+    }
+}
+
+impl FromStr for ComposeTable {
+    type Err = ComposeError;
+
+    fn from_str(text: &str) -> Result<Self, ComposeError> {
+        let mut table = Self::new();
+
+        'lines: for (line, text) in text.lines().enumerate() {
+            // Column of a byte offset into the line
+            let error = |offset: usize, kind| ComposeError {
+                line: line + 1,
+                column: text[..offset].chars().count() + 1,
+                kind,
+            };
+            let trimmed = text.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+            if trimmed.starts_with("include") {
+                continue;
+            }
+            let colon = text.find(':').ok_or_else(|| {
+                error(text.trim_end().len(), ComposeErrorKind::MissingColon)
+            })?;
+            let result = &text[colon + 1..];
+            let offset = colon + 1 + result.len() - result.trim_start().len();
+            let result = parse_result(&text[offset..]).map_err(|at| {
+                error(offset + at, ComposeErrorKind::InvalidResult)
+            })?;
+
+            let mut sequence = String::new();
+            let mut first = true;
+            let mut offset = 0;
+            while let Some(start) = text[offset..colon].find('<') {
+                let start = offset + start;
+                let end = text[start..colon].find('>').ok_or_else(|| {
+                    error(start, ComposeErrorKind::UnterminatedKeysym)
+                })?;
+                let keysym = &text[start + 1..start + end];
+                offset = start + end + 1;
+                if first {
+                    if keysym != "Multi_key" {
+                        continue 'lines;
+                    }
+                    first = false;
+                    continue;
+                }
+                match keysym_char(keysym) {
+                    Some(c) => sequence.push(c),
+                    None => continue 'lines,
+                }
+            }
+            if !sequence.is_empty() {
+                table.insert(&sequence, &result);
+            }
+        }
+
+        Ok(table)
+    }
+}
+
+/// Parse the quoted result string (ignoring any keysym after it), failing
+/// with the byte offset of the error
+fn parse_result(text: &str) -> Result<String, usize> {
+    if !text.starts_with('"') {
+        return Err(0);
+    }
+    let mut chars = text.char_indices().skip(1).peekable();
+    let mut result = Vec::new();
+    let mut bytes = [0; 4];
+
+    loop {
+        let (i, c) = chars.next().ok_or(text.len())?;
+        let c = match c {
+            '"' => return String::from_utf8(result).map_err(|_| 0),
+            '\\' => chars.next().ok_or(text.len())?.1,
+            c => {
+                result.extend_from_slice(c.encode_utf8(&mut bytes).as_bytes());
+                continue;
+            }
+        };
+        // Octal (up to 3 digits) or hexadecimal (up to 2 digits) byte
+        let (radix, max, mut value, mut digits) = match c {
+            'x' | 'X' => (16, 2, 0, 0),
+            '0'..='7' => (8, 3, c.to_digit(8).unwrap(), 1),
+            c => {
+                result.extend_from_slice(c.encode_utf8(&mut bytes).as_bytes());
+                continue;
+            }
+        };
+        while digits < max {
+            match chars.peek().and_then(|(_, c)| c.to_digit(radix)) {
+                Some(digit) => value = value * radix + digit,
+                None => break,
+            }
+            chars.next();
+            digits += 1;
+        }
+        match u8::try_from(value) {
+            Ok(byte) if digits != 0 && byte != 0 => result.push(byte),
+            _ => return Err(i),
+        }
+    }
+}
+
+/// Get the character typed by an X11 keysym name
+fn keysym_char(keysym: &str) -> Option<char> {
+    let mut chars = keysym.chars();
+    let first = chars.next()?;
+    if chars.next().is_none() && first.is_ascii_alphanumeric() {
+        return Some(first);
+    }
+    if let Some(hex) = keysym.strip_prefix('U') {
+        if let Ok(code) = u32::from_str_radix(hex, 16) {
+            return char::from_u32(code);
+        }
+    }
+    if let Some(&(_, c)) = ASCII.iter().find(|(name, _)| *name == keysym) {
+        return Some(c);
+    }
+    LATIN1
+        .iter()
+        .position(|name| *name == keysym)
+        .and_then(|i| char::from_u32(0xA0 + i as u32))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(
+        line: usize,
+        column: usize,
+        kind: ComposeErrorKind,
+    ) -> ComposeError {
+        ComposeError { line, column, kind }
+    }
+
+    #[test]
+    fn sequences() {
+        let table: ComposeTable = "# Comment\n\
+            include \"%L\"\n\
+            \n\
+            <Multi_key> <a> <e> : \"æ\" ae\n\
+            <Multi_key> <apostrophe> <E> : \"É\" Eacute\n\
+            <Multi_key> <U2190> <U2192> : \"↔\"\n\
+            <Multi_key> <exclam> <exclam> : \"¡\" exclamdown\n\
+            <Multi_key> <o> <ssharp> : \"ẞ\"\n\
+            <dead_acute> <a> : \"á\" aacute\n\
+            <Multi_key> <Left> <Right> : \"?\"\n"
+            .parse()
+            .unwrap();
+
+        assert_eq!(table.get("ae"), Some("æ"));
+        assert_eq!(table.get("'E"), Some("É"));
+        assert_eq!(table.get("←→"), Some("↔"));
+        assert_eq!(table.get("!!"), Some("¡"));
+        assert_eq!(table.get("oß"), Some("ẞ"));
+        assert_eq!(table.get("a"), None);
+        assert!(table.is_prefix("a"));
+        assert!(table.is_prefix("ae"));
+        assert!(!table.is_prefix("e"));
+        assert!(!table.is_prefix("aee"));
+    }
+
+    #[test]
+    fn escapes() {
+        for (result, expected) in [
+            (r#""\"""#, "\""),
+            (r#""\\""#, "\\"),
+            (r#""\x41\x4a""#, "AJ"),
+            (r#""\X4""#, "\x04"),
+            (r#""\101\1012""#, "AA2"),
+            (r#""\303\251""#, "é"),
+            (r#""\xc3\xA9x""#, "éx"),
+            (r#""\q""#, "q"),
+        ] {
+            let line = format!("<Multi_key> <a> <b> : {}", result);
+            let table: ComposeTable = line.parse().unwrap();
+            assert_eq!(table.get("ab"), Some(expected), "{}", result);
+        }
+    }
+
+    #[test]
+    fn errors() {
+        for (text, expected) in [
+            (
+                "<Multi_key> <a> <b> \"x\"",
+                error(1, 24, ComposeErrorKind::MissingColon),
+            ),
+            (
+                "\n  <Multi_key> <a : \"x\"",
+                error(2, 15, ComposeErrorKind::UnterminatedKeysym),
+            ),
+            (
+                "<Multi_key> <a> <b> : x",
+                error(1, 23, ComposeErrorKind::InvalidResult),
+            ),
+            (
+                "<Multi_key> <a> <b> : \"x",
+                error(1, 25, ComposeErrorKind::InvalidResult),
+            ),
+            (
+                r#"<Multi_key> <a> <b> : "x\400""#,
+                error(1, 25, ComposeErrorKind::InvalidResult),
+            ),
+            (
+                r#"<Multi_key> <a> <b> : "\xg""#,
+                error(1, 24, ComposeErrorKind::InvalidResult),
+            ),
+            (
+                r#"<Multi_key> <a> <b> : "\0""#,
+                error(1, 24, ComposeErrorKind::InvalidResult),
+            ),
+            (
+                r#"<Multi_key> <a> <b> : "\303""#,
+                error(1, 23, ComposeErrorKind::InvalidResult),
+            ),
+        ] {
+            assert_eq!(text.parse::<ComposeTable>().unwrap_err(), expected);
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use std::fmt;
+
+use pasts::prelude::*;
+
+use crate::{Keyboard, Type};
+
+/// Error decoding a typing event sent to a [`Keyboard`], or read in the wire
+/// or [`Recording`](crate::Recording) binary format
+///
+/// Errors marked as strict are only returned when decoding strictly (see
+/// [`Keyboard::set_strict()`], [`Decoder::set_strict()`] and
+/// [`Recording::from_bytes_lenient()`]), otherwise the event is repaired.
+///
+/// [`Decoder::set_strict()`]: crate::Decoder::set_strict
+/// [`Recording::from_bytes_lenient()`]: crate::Recording::from_bytes_lenient
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum DecodeError {
+    /// Key event with an unknown key state byte
+    InvalidKeyState(u8),
+    /// Text event with an unknown kind byte
+    InvalidTextKind(u8),
+    /// Character that isn't valid UTF-8
+    InvalidUtf8([u8; 4]),
+    /// Character followed by bytes that aren't zero (strict, otherwise
+    /// they're ignored)
+    TrailingBytes([u8; 4]),
+    /// Lock event with unknown lock bits set (strict, otherwise they're
+    /// cleared)
+    InvalidLocks(u8),
+    /// Preedit cursor past the end of the text, or not on a character
+    /// boundary (strict, otherwise it's moved back to one)
+    InvalidCursor(usize),
+    /// Lock, commit or cancel event with header bytes that aren't zero where
+    /// they're unused (strict, otherwise they're ignored)
+    InvalidHeader([u8; 4]),
+    /// Cancel event with text, of this length (strict, otherwise it's
+    /// ignored)
+    UnexpectedText(usize),
+    /// Wire format ended before the end of the event
+    Truncated,
+    /// Wire format with bytes left over after the event
+    ExtraBytes(usize),
+    /// Wire format text length over 64 KiB
+    InvalidLength,
+    /// Wire format text that isn't valid UTF-8
+    InvalidText,
+    /// Wire format stream that doesn't start with the `KBRD` magic bytes
+    InvalidMagic,
+    /// Wire format version that isn't supported
+    UnsupportedVersion(u8),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::InvalidKeyState(state) => {
+                write!(f, "invalid key state {:02X}", state)
+            }
+            DecodeError::InvalidTextKind(kind) => {
+                write!(f, "invalid text kind {:02X}", kind)
+            }
+            DecodeError::InvalidUtf8(bytes) => {
+                write!(f, "invalid UTF-8 {:02X?}", bytes)
+            }
+            DecodeError::TrailingBytes(bytes) => {
+                write!(f, "trailing bytes after character {:02X?}", bytes)
+            }
+            DecodeError::InvalidLocks(locks) => {
+                write!(f, "invalid locks {:02X}", locks)
+            }
+            DecodeError::InvalidCursor(cursor) => {
+                write!(f, "invalid preedit cursor {}", cursor)
+            }
+            DecodeError::InvalidHeader(header) => {
+                write!(f, "unused header bytes set {:02X?}", header)
+            }
+            DecodeError::UnexpectedText(len) => {
+                write!(f, "{} bytes of text in cancel event", len)
+            }
+            DecodeError::Truncated => write!(f, "truncated event"),
+            DecodeError::ExtraBytes(len) => {
+                write!(f, "{} extra bytes after event", len)
+            }
+            DecodeError::InvalidLength => write!(f, "invalid text length"),
+            DecodeError::InvalidText => write!(f, "invalid UTF-8 text"),
+            DecodeError::InvalidMagic => write!(f, "invalid magic bytes"),
+            DecodeError::UnsupportedVersion(version) => {
+                write!(f, "unsupported version {}", version)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+impl From<DecodeError> for std::io::Error {
+    fn from(error: DecodeError) -> Self {
+        Self::new(std::io::ErrorKind::InvalidData, error)
+    }
+}
+
+/// Notifier yielding a [`Keyboard`]'s events, or the errors decoding the
+/// events sent to it, returned by [`Keyboard::decoded()`]
+#[derive(Debug)]
+pub struct Decoded<'a>(pub(crate) &'a mut Keyboard);
+
+impl Notifier for Decoded<'_> {
+    type Event = Result<Type, DecodeError>;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        exec: &mut Exec<'_>,
+    ) -> Poll<Self::Event> {
+        self.0.poll_decoded(exec).map(|event| event.map(|e| e.typed))
+    }
+}
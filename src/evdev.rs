@@ -0,0 +1,444 @@
+//! Linux evdev input backend.
+
+use std::{
+    fs::File,
+    io::{ErrorKind, Read, Result},
+    path::Path,
+};
+
+use crate::{Key, Mods, Type, Typer};
+
+/// Event type for key and button state changes (`EV_KEY`)
+const EV_KEY: u16 = 0x01;
+
+/// Size of a `struct input_event` (a timestamp, whose size depends on the
+/// target's `time_t`, followed by `__u16 type`, `__u16 code` and
+/// `__s32 value`)
+const EVENT_SIZE: usize = std::mem::size_of::<libc::input_event>();
+
+/// Modifier keys tracked by the reader, in the order of their held bits
+const MODIFIERS: [Key; 7] = [
+    Key::LeftShift,
+    Key::RightShift,
+    Key::LeftApp,
+    Key::RightApp,
+    Key::LeftPrg,
+    Key::RightPrg,
+    Key::Graph,
+];
+
+/// Keyboard event source reading `input_event` structs from an evdev device
+/// (`/dev/input/event*`), or any other reader producing the same byte stream.
+///
+/// Events are decoded in native byte order, so recorded streams can be
+/// replayed through [`Evdev::new()`] without real hardware.
+#[derive(Debug)]
+pub struct Evdev<R: Read = File> {
+    reader: R,
+    held: u8,
+}
+
+impl Evdev {
+    /// Open an evdev device file, such as `/dev/input/event0`
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> Evdev<R> {
+    /// Create a new evdev event source from a reader of `input_event` structs
+    pub fn new(reader: R) -> Self {
+        let held = 0;
+
+        Self { reader, held }
+    }
+
+    /// Get the modifier state derived from the modifier keys held so far
+    pub fn mods(&self) -> Mods {
+        let held = |key| {
+            let bit = MODIFIERS.iter().position(|k| *k == key).unwrap();
+            self.held & (1 << bit) != 0
+        };
+        let mut mods = Mods::new();
+
+        mods.set_shift(held(Key::LeftShift) || held(Key::RightShift));
+        mods.set_app(held(Key::LeftApp) || held(Key::RightApp));
+        mods.set_prg(held(Key::LeftPrg) || held(Key::RightPrg));
+        mods.set_graph(held(Key::Graph));
+        mods
+    }
+
+    /// Read the next key event, blocking until one is available.
+    ///
+    /// Events that aren't `EV_KEY` and codes with no matching [`Key`] are
+    /// skipped.  Returns `None` once the reader reaches the end
+    /// of the stream, or fails with [`ErrorKind::UnexpectedEof`] if it ends
+    /// partway through an event.
+    pub fn read(&mut self) -> Result<Option<Type>> {
+        let mut event = [0; EVENT_SIZE];
+
+        loop {
+            if !self.read_event(&mut event)? {
+                return Ok(None);
+            }
+
+            let fields = &event[EVENT_SIZE - 8..];
+            let kind = u16::from_ne_bytes([fields[0], fields[1]]);
+            let code = u16::from_ne_bytes([fields[2], fields[3]]);
+            let value = i32::from_ne_bytes([
+                fields[4], fields[5], fields[6], fields[7],
+            ]);
+
+            if kind != EV_KEY {
+                continue;
+            }
+            let key = match key(code) {
+                Some(key) => key,
+                None => continue,
+            };
+            if let Some(bit) = MODIFIERS.iter().position(|k| *k == key) {
+                match value {
+                    0 => self.held &= !(1 << bit),
+                    1 => self.held |= 1 << bit,
+                    _ => {}
+                }
+            }
+
+            let mods = self.mods();
+            return Ok(Some(match value {
+                0 => Type::Release(key, mods),
+                1 => Type::Press(key, mods),
+                _ => Type::Repeat(key, mods),
+            }));
+        }
+    }
+
+    /// Read a whole event, returning `false` if the stream ended before it
+    fn read_event(&mut self, event: &mut [u8]) -> Result<bool> {
+        let mut filled = 0;
+
+        while filled < event.len() {
+            match self.reader.read(&mut event[filled..]) {
+                Ok(0) if filled == 0 => return Ok(false),
+                Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+                Ok(len) => filled += len,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(true)
+    }
+
+    /// Forward all key events to a [`Typer`] until the end of the stream.
+    ///
+    /// Reading blocks the current thread, so this should usually be run on
+    /// an executor dedicated to the device.  When forwarding several devices
+    /// to one keyboard, give each a [`Keyboard::device_typer()`] so their key
+    /// state is tracked separately.
+    ///
+    /// [`Keyboard::device_typer()`]: crate::Keyboard::device_typer
+    pub async fn forward(mut self, typer: &Typer) -> Result<()> {
+        while let Some(typed) = self.read()? {
+            typer.send(typed).await;
+        }
+        Ok(())
+    }
+}
+
+/// Translate a Linux key code (`KEY_*` / `BTN_*`) into a [`Key`]
+fn key(code: u16) -> Option<Key> {
+    use Key::*;
+
+    Some(match code {
+        1 => Escape,
+        2 => Digit1,
+        3 => Digit2,
+        4 => Digit3,
+        5 => Digit4,
+        6 => Digit5,
+        7 => Digit6,
+        8 => Digit7,
+        9 => Digit8,
+        10 => Digit9,
+        11 => Digit0,
+        12 => Minus,
+        13 => Equals,
+        14 => Erase,
+        15 => Tab,
+        16 => Q,
+        17 => W,
+        18 => E,
+        19 => R,
+        20 => T,
+        21 => Y,
+        22 => U,
+        23 => I,
+        24 => O,
+        25 => P,
+        26 => LeftBracket,
+        27 => RightBracket,
+        28 => Enter,
+        29 => LeftApp,
+        30 => A,
+        31 => S,
+        32 => D,
+        33 => F,
+        34 => G,
+        35 => H,
+        36 => J,
+        37 => K,
+        38 => L,
+        39 => Semicolon,
+        40 => Apostrophe,
+        41 => Grave,
+        42 => LeftShift,
+        43 => Backslash,
+        44 => Z,
+        45 => X,
+        46 => C,
+        47 => V,
+        48 => B,
+        49 => N,
+        50 => M,
+        51 => Comma,
+        52 => Period,
+        53 => Slash,
+        54 => RightShift,
+        55 => NumMultiply,
+        56 => LeftPrg,
+        57 => Space,
+        59 => F1,
+        60 => F2,
+        61 => F3,
+        62 => F4,
+        63 => F5,
+        64 => F6,
+        65 => F7,
+        66 => F8,
+        67 => F9,
+        68 => F10,
+        69 => NumLock,
+        70 => ScrollLock,
+        71 => Num7,
+        72 => Num8,
+        73 => Num9,
+        74 => NumSubtract,
+        75 => Num4,
+        76 => Num5,
+        77 => Num6,
+        78 => NumAdd,
+        79 => Num1,
+        80 => Num2,
+        81 => Num3,
+        82 => Num0,
+        83 => NumDecimalPoint,
+        85 => Kanji,
+        87 => F11,
+        88 => F12,
+        92 => Convert,
+        93 => Kana,
+        94 => NonConvert,
+        95 => NumComma,
+        96 => NumEnter,
+        97 => RightApp,
+        98 => NumDivide,
+        99 => ScreenShot,
+        // AltGr on most layouts
+        100 => Graph,
+        102 => PageHome,
+        103 => Up,
+        104 => PageUp,
+        105 => Left,
+        106 => Right,
+        107 => PageEnd,
+        108 => Down,
+        109 => PageDown,
+        110 => Insert,
+        111 => Remove,
+        113 => SpeakerMute,
+        114 => SpeakerQuieter,
+        115 => SpeakerLouder,
+        116 => Power,
+        119 => Pause,
+        121 => NumComma,
+        122 => Hangul,
+        123 => Hanja,
+        125 => LeftSys,
+        126 => RightSys,
+        127 => Compose,
+        138 => Help,
+        139 => Menu,
+        140 => LaunchCalculator,
+        150 => LaunchWeb,
+        152 => SysLock,
+        155 => LaunchEmail,
+        158 => Back,
+        159 => Forward,
+        161 => StopEject,
+        163 => Next,
+        164 => Play,
+        165 => Prev,
+        166 => StopEject,
+        168 => Rewind,
+        169 => Phone,
+        171 => LaunchSettings,
+        177 => ScrollUp,
+        178 => ScrollDown,
+        181 => NewWindow,
+        183 => F13,
+        184 => F14,
+        185 => F15,
+        186 => F16,
+        187 => F17,
+        188 => F18,
+        189 => F19,
+        190 => F20,
+        191 => F21,
+        192 => F22,
+        193 => F23,
+        194 => F24,
+        207 => Play,
+        208 => FastForward,
+        212 => MobileLaunchCamera,
+        215 => LaunchEmail,
+        219 => LaunchFinance,
+        224 => Dim,
+        225 => Brighten,
+        226 => LaunchPlayer,
+        227 => Display,
+        248 => MicrophoneMute,
+        0x110 => LeftClick,
+        0x111 => RightClick,
+        0x112 => MiddleClick,
+        0x113 => SideClick,
+        0x163 => Clear,
+        0x189 => LaunchVideo,
+        0x18d => LaunchCalendar,
+        0x19b => Break,
+        0x1a1 => LaunchGame,
+        0x1a2 => ZoomIn,
+        0x1a3 => ZoomOut,
+        0x1a4 => ZoomReset,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EV_SYN: u16 = 0x00;
+    const EV_MSC: u16 = 0x04;
+
+    /// Encode an `input_event` (with a zero timestamp)
+    fn event(kind: u16, code: u16, value: i32) -> Vec<u8> {
+        let mut bytes = vec![0; EVENT_SIZE - 8];
+
+        bytes.extend_from_slice(&kind.to_ne_bytes());
+        bytes.extend_from_slice(&code.to_ne_bytes());
+        bytes.extend_from_slice(&value.to_ne_bytes());
+        bytes
+    }
+
+    /// Read all of the events from a byte stream
+    fn read_all(bytes: &[u8]) -> Vec<String> {
+        let mut evdev = Evdev::new(bytes);
+        let mut events = Vec::new();
+
+        while let Some(typed) = evdev.read().unwrap() {
+            events.push(format!("{:?}", typed));
+        }
+        events
+    }
+
+    #[test]
+    fn press_release() {
+        let bytes = [
+            event(EV_KEY, 42, 1),
+            event(EV_SYN, 0, 0),
+            event(EV_KEY, 30, 1),
+            event(EV_KEY, 30, 0),
+            event(EV_KEY, 42, 0),
+        ]
+        .concat();
+
+        assert_eq!(
+            read_all(&bytes),
+            [
+                "Press(LeftShift, Mods(8))",
+                "Press(A, Mods(8))",
+                "Release(A, Mods(8))",
+                "Release(LeftShift, Mods(0))",
+            ],
+        );
+    }
+
+    #[test]
+    fn repeat() {
+        let bytes = [
+            event(EV_KEY, 103, 1),
+            event(EV_KEY, 103, 2),
+            event(EV_KEY, 103, 2),
+            event(EV_KEY, 103, 0),
+        ]
+        .concat();
+
+        assert_eq!(
+            read_all(&bytes),
+            [
+                "Press(Up, Mods(0))",
+                "Repeat(Up, Mods(0))",
+                "Repeat(Up, Mods(0))",
+                "Release(Up, Mods(0))",
+            ],
+        );
+    }
+
+    #[test]
+    fn ignored() {
+        let bytes = [
+            event(EV_MSC, 4, 0x70004),
+            event(EV_SYN, 0, 0),
+            // Caps lock, and a code with no matching key
+            event(EV_KEY, 58, 1),
+            event(EV_KEY, 0x2ff, 1),
+            event(EV_KEY, 1, 1),
+        ]
+        .concat();
+
+        assert_eq!(read_all(&bytes), ["Press(Escape, Mods(0))"]);
+    }
+
+    /// Pressing and releasing A, as recorded from `/dev/input/event0`
+    #[test]
+    #[cfg(all(target_pointer_width = "64", target_endian = "little"))]
+    fn recorded() {
+        const RECORDED: &[u8] = &[
+            0xa3, 0x1d, 0x2e, 0x65, 0, 0, 0, 0, 0x5c, 0x3b, 0x0b, 0, 0, 0, 0,
+            0, 0x04, 0, 0x04, 0, 0x04, 0, 0x07, 0, 0xa3, 0x1d, 0x2e, 0x65, 0,
+            0, 0, 0, 0x5c, 0x3b, 0x0b, 0, 0, 0, 0, 0, 0x01, 0, 0x1e, 0, 0x01,
+            0, 0, 0, 0xa3, 0x1d, 0x2e, 0x65, 0, 0, 0, 0, 0x5c, 0x3b, 0x0b, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xa3, 0x1d, 0x2e, 0x65, 0, 0,
+            0, 0, 0x31, 0x9e, 0x0c, 0, 0, 0, 0, 0, 0x04, 0, 0x04, 0, 0x04, 0,
+            0x07, 0, 0xa3, 0x1d, 0x2e, 0x65, 0, 0, 0, 0, 0x31, 0x9e, 0x0c, 0,
+            0, 0, 0, 0, 0x01, 0, 0x1e, 0, 0, 0, 0, 0, 0xa3, 0x1d, 0x2e, 0x65,
+            0, 0, 0, 0, 0x31, 0x9e, 0x0c, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0,
+        ];
+
+        assert_eq!(
+            read_all(RECORDED),
+            ["Press(A, Mods(0))", "Release(A, Mods(0))"],
+        );
+    }
+
+    #[test]
+    fn truncated() {
+        let bytes = event(EV_KEY, 30, 1);
+        let mut evdev = Evdev::new(&bytes[..bytes.len() - 1]);
+        let error = evdev.read().unwrap_err();
+
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+        assert!(Evdev::new(&[][..]).read().unwrap().is_none());
+    }
+}
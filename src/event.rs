@@ -0,0 +1,42 @@
+use std::time::Instant;
+
+use pasts::prelude::*;
+
+use crate::{Keyboard, Type};
+
+/// Typing event with the time it happened, and the device it came from
+#[derive(Clone, Debug)]
+pub struct Event {
+    /// The typing event
+    pub typed: Type,
+    /// When the event happened (set by the [`Typer`](crate::Typer), or when
+    /// the [`Keyboard`] received it).  Events generated by the keyboard have
+    /// the time of the event they were generated from.
+    pub time: Instant,
+    /// Identifier of the device the event came from, if known
+    pub device: Option<u32>,
+}
+
+impl Event {
+    /// Create a new event happening now, from an unknown device
+    pub fn new(typed: Type) -> Self {
+        Self {
+            typed,
+            time: Instant::now(),
+            device: None,
+        }
+    }
+}
+
+/// Notifier yielding a [`Keyboard`]'s events as timestamped [`Event`]s,
+/// returned by [`Keyboard::events()`]
+#[derive(Debug)]
+pub struct Events<'a>(pub(crate) &'a mut Keyboard);
+
+impl Notifier for Events<'_> {
+    type Event = Event;
+
+    fn poll_next(mut self: Pin<&mut Self>, exec: &mut Exec<'_>) -> Poll<Event> {
+        self.0.poll_event(exec)
+    }
+}
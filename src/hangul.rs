@@ -0,0 +1,295 @@
+//! 2-set (Dubeolsik) Hangul input method.
+
+use crate::{Key, Mods};
+
+/// Initial consonants (choseong), in syllable order
+const INITIALS: &str = "ㄱㄲㄴㄷㄸㄹㅁㅂㅃㅅㅆㅇㅈㅉㅊㅋㅌㅍㅎ";
+
+/// Vowels (jungseong), in syllable order
+const VOWELS: &str = "ㅏㅐㅑㅒㅓㅔㅕㅖㅗㅘㅙㅚㅛㅜㅝㅞㅟㅠㅡㅢㅣ";
+
+/// Final consonants (jongseong), in syllable order (after no final)
+const FINALS: &str = "ㄱㄲㄳㄴㄵㄶㄷㄹㄺㄻㄼㄽㄾㄿㅀㅁㅂㅄㅅㅆㅇㅈㅊㅋㅌㅍㅎ";
+
+/// Compound vowels and final consonants, and the jamo they're made from
+const COMPOUNDS: [(char, char, char); 18] = [
+    ('ㅗ', 'ㅏ', 'ㅘ'),
+    ('ㅗ', 'ㅐ', 'ㅙ'),
+    ('ㅗ', 'ㅣ', 'ㅚ'),
+    ('ㅜ', 'ㅓ', 'ㅝ'),
+    ('ㅜ', 'ㅔ', 'ㅞ'),
+    ('ㅜ', 'ㅣ', 'ㅟ'),
+    ('ㅡ', 'ㅣ', 'ㅢ'),
+    ('ㄱ', 'ㅅ', 'ㄳ'),
+    ('ㄴ', 'ㅈ', 'ㄵ'),
+    ('ㄴ', 'ㅎ', 'ㄶ'),
+    ('ㄹ', 'ㄱ', 'ㄺ'),
+    ('ㄹ', 'ㅁ', 'ㄻ'),
+    ('ㄹ', 'ㅂ', 'ㄼ'),
+    ('ㄹ', 'ㅅ', 'ㄽ'),
+    ('ㄹ', 'ㅌ', 'ㄾ'),
+    ('ㄹ', 'ㅍ', 'ㄿ'),
+    ('ㄹ', 'ㅎ', 'ㅀ'),
+    ('ㅂ', 'ㅅ', 'ㅄ'),
+];
+
+/// Get the jamo typed by a key on the Dubeolsik layout
+fn jamo(key: Key, shift: bool) -> Option<char> {
+    use Key::*;
+
+    Some(match (key, shift) {
+        (Q, false) => 'ㅂ',
+        (Q, true) => 'ㅃ',
+        (W, false) => 'ㅈ',
+        (W, true) => 'ㅉ',
+        (E, false) => 'ㄷ',
+        (E, true) => 'ㄸ',
+        (R, false) => 'ㄱ',
+        (R, true) => 'ㄲ',
+        (T, false) => 'ㅅ',
+        (T, true) => 'ㅆ',
+        (Y, _) => 'ㅛ',
+        (U, _) => 'ㅕ',
+        (I, _) => 'ㅑ',
+        (O, false) => 'ㅐ',
+        (O, true) => 'ㅒ',
+        (P, false) => 'ㅔ',
+        (P, true) => 'ㅖ',
+        (A, _) => 'ㅁ',
+        (S, _) => 'ㄴ',
+        (D, _) => 'ㅇ',
+        (F, _) => 'ㄹ',
+        (G, _) => 'ㅎ',
+        (H, _) => 'ㅗ',
+        (J, _) => 'ㅓ',
+        (K, _) => 'ㅏ',
+        (L, _) => 'ㅣ',
+        (Z, _) => 'ㅋ',
+        (X, _) => 'ㅌ',
+        (C, _) => 'ㅊ',
+        (V, _) => 'ㅍ',
+        (B, _) => 'ㅠ',
+        (N, _) => 'ㅜ',
+        (M, _) => 'ㅡ',
+        _ => return None,
+    })
+}
+
+/// Combine two jamo into a compound vowel or final consonant
+fn combine(first: char, second: char) -> Option<char> {
+    COMPOUNDS
+        .iter()
+        .find(|(a, b, _)| *a == first && *b == second)
+        .map(|(_, _, c)| *c)
+}
+
+/// Split a compound vowel or final consonant into its two jamo
+fn split(compound: char) -> Option<(char, char)> {
+    COMPOUNDS
+        .iter()
+        .find(|(_, _, c)| *c == compound)
+        .map(|(a, b, _)| (*a, *b))
+}
+
+/// Index of a jamo in a list
+fn index(list: &str, jamo: char) -> Option<u32> {
+    list.chars().position(|c| c == jamo).map(|i| i as u32)
+}
+
+/// Hangul syllable being composed
+#[derive(Debug, Default)]
+pub(crate) struct Hangul {
+    initial: Option<char>,
+    vowel: Option<char>,
+    last: Option<char>,
+}
+
+impl Hangul {
+    /// Create a new input method with nothing being composed
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the syllable (or lone jamo) being composed
+    pub(crate) fn preedit(&self) -> Option<char> {
+        let (initial, vowel) = match (self.initial, self.vowel) {
+            (Some(initial), Some(vowel)) => (initial, vowel),
+            (initial, vowel) => return initial.or(vowel),
+        };
+        let initial = index(INITIALS, initial)?;
+        let vowel = index(VOWELS, vowel)?;
+        let last = self
+            .last
+            .and_then(|c| index(FINALS, c))
+            .map_or(0, |i| i + 1);
+
+        char::from_u32(0xAC00 + (initial * 21 + vowel) * 28 + last)
+    }
+
+    /// Commit the syllable being composed
+    pub(crate) fn flush(&mut self, commit: &mut String) {
+        commit.extend(self.preedit());
+        *self = Self::new();
+    }
+
+    /// Handle a key press, appending any completed syllables to `commit`.
+    ///
+    /// Returns `false` if the key should be handled as if the input method
+    /// wasn't active.
+    pub(crate) fn press(
+        &mut self,
+        key: Key,
+        mods: Mods,
+        commit: &mut String,
+    ) -> bool {
+        match key {
+            Key::LeftShift | Key::RightShift | Key::Graph | Key::Hangul => {
+                return false
+            }
+            Key::Erase if self.preedit().is_some() => {
+                self.erase();
+                return true;
+            }
+            _ => {}
+        }
+        let jamo = match jamo(key, mods.shift()) {
+            Some(jamo) if !mods.app() && !mods.prg() => jamo,
+            _ => {
+                self.flush(commit);
+                return false;
+            }
+        };
+
+        if index(VOWELS, jamo).is_some() {
+            self.vowel(jamo, commit);
+        } else {
+            self.consonant(jamo, commit);
+        }
+        true
+    }
+
+    fn consonant(&mut self, jamo: char, commit: &mut String) {
+        match (self.initial, self.vowel, self.last) {
+            (Some(_), Some(_), None) if index(FINALS, jamo).is_some() => {
+                self.last = Some(jamo);
+            }
+            (Some(_), Some(_), Some(last)) => match combine(last, jamo) {
+                Some(compound) => self.last = Some(compound),
+                None => {
+                    self.flush(commit);
+                    self.initial = Some(jamo);
+                }
+            },
+            _ => {
+                self.flush(commit);
+                self.initial = Some(jamo);
+            }
+        }
+    }
+
+    fn vowel(&mut self, jamo: char, commit: &mut String) {
+        match (self.initial, self.vowel, self.last) {
+            (_, None, _) => self.vowel = Some(jamo),
+            (_, Some(vowel), None) => match combine(vowel, jamo) {
+                Some(compound) => self.vowel = Some(compound),
+                None => {
+                    self.flush(commit);
+                    self.vowel = Some(jamo);
+                }
+            },
+            // The final consonant moves to start the next syllable
+            (_, Some(_), Some(last)) => {
+                let (last, initial) = match split(last) {
+                    Some((first, second)) => (Some(first), second),
+                    None => (None, last),
+                };
+                self.last = last;
+                self.flush(commit);
+                self.initial = Some(initial);
+                self.vowel = Some(jamo);
+            }
+        }
+    }
+
+    fn erase(&mut self) {
+        let part = if self.last.is_some() {
+            &mut self.last
+        } else if self.vowel.is_some() {
+            &mut self.vowel
+        } else {
+            &mut self.initial
+        };
+        *part = part.and_then(split).map(|(first, _)| first);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Type keys, returning the committed text and the syllable being composed
+    fn typed(hangul: &mut Hangul, keys: &[Key]) -> (String, Option<char>) {
+        let mut commit = String::new();
+
+        for key in keys {
+            assert!(hangul.press(*key, Mods::new(), &mut commit));
+        }
+        (commit, hangul.preedit())
+    }
+
+    #[test]
+    fn syllables() {
+        let mut hangul = Hangul::new();
+
+        assert_eq!(typed(&mut hangul, &[Key::G]), (String::new(), Some('ㅎ')));
+        assert_eq!(typed(&mut hangul, &[Key::K]), (String::new(), Some('하')));
+        assert_eq!(typed(&mut hangul, &[Key::S]), (String::new(), Some('한')));
+        // A consonant that can't be added to the final starts a new syllable
+        assert_eq!(typed(&mut hangul, &[Key::R]), ("한".into(), Some('ㄱ')));
+        assert_eq!(
+            typed(&mut hangul, &[Key::M, Key::F]),
+            (String::new(), Some('글')),
+        );
+        // A vowel moves the final consonant to the next syllable
+        assert_eq!(typed(&mut hangul, &[Key::K]), ("그".into(), Some('라')));
+    }
+
+    #[test]
+    fn compounds() {
+        let mut hangul = Hangul::new();
+
+        assert_eq!(
+            typed(&mut hangul, &[Key::R, Key::H, Key::K]),
+            (String::new(), Some('과')),
+        );
+        assert_eq!(typed(&mut hangul, &[Key::M]), ("과".into(), Some('ㅡ')));
+
+        let mut hangul = Hangul::new();
+
+        assert_eq!(
+            typed(&mut hangul, &[Key::E, Key::K, Key::F, Key::R]),
+            (String::new(), Some('닭')),
+        );
+        // Only the second part of a compound final moves
+        assert_eq!(typed(&mut hangul, &[Key::K]), ("달".into(), Some('가')));
+    }
+
+    #[test]
+    fn erase() {
+        let mut hangul = Hangul::new();
+        let erase = |hangul: &mut Hangul| {
+            assert!(hangul.press(Key::Erase, Mods::new(), &mut String::new()));
+            hangul.preedit()
+        };
+
+        typed(&mut hangul, &[Key::R, Key::H, Key::K, Key::F, Key::R]);
+        assert_eq!(hangul.preedit(), Some('괅'));
+        assert_eq!(erase(&mut hangul), Some('괄'));
+        assert_eq!(erase(&mut hangul), Some('과'));
+        assert_eq!(erase(&mut hangul), Some('고'));
+        assert_eq!(erase(&mut hangul), Some('ㄱ'));
+        assert_eq!(erase(&mut hangul), None);
+        // Nothing left to erase
+        assert!(!hangul.press(Key::Erase, Mods::new(), &mut String::new()));
+    }
+}
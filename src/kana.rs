@@ -0,0 +1,425 @@
+//! Japanese kana (wāpuro romaji) input method.
+
+/// Wāpuro romaji to hiragana
+const ROMAJI: &[(&str, &str)] = &[
+    ("a", "あ"),
+    ("i", "い"),
+    ("u", "う"),
+    ("e", "え"),
+    ("o", "お"),
+    ("ka", "か"),
+    ("ki", "き"),
+    ("ku", "く"),
+    ("ke", "け"),
+    ("ko", "こ"),
+    ("ga", "が"),
+    ("gi", "ぎ"),
+    ("gu", "ぐ"),
+    ("ge", "げ"),
+    ("go", "ご"),
+    ("sa", "さ"),
+    ("si", "し"),
+    ("shi", "し"),
+    ("su", "す"),
+    ("se", "せ"),
+    ("so", "そ"),
+    ("za", "ざ"),
+    ("zi", "じ"),
+    ("ji", "じ"),
+    ("zu", "ず"),
+    ("ze", "ぜ"),
+    ("zo", "ぞ"),
+    ("ta", "た"),
+    ("ti", "ち"),
+    ("chi", "ち"),
+    ("tu", "つ"),
+    ("tsu", "つ"),
+    ("te", "て"),
+    ("to", "と"),
+    ("da", "だ"),
+    ("di", "ぢ"),
+    ("du", "づ"),
+    ("de", "で"),
+    ("do", "ど"),
+    ("na", "な"),
+    ("ni", "に"),
+    ("nu", "ぬ"),
+    ("ne", "ね"),
+    ("no", "の"),
+    ("ha", "は"),
+    ("hi", "ひ"),
+    ("hu", "ふ"),
+    ("fu", "ふ"),
+    ("he", "へ"),
+    ("ho", "ほ"),
+    ("ba", "ば"),
+    ("bi", "び"),
+    ("bu", "ぶ"),
+    ("be", "べ"),
+    ("bo", "ぼ"),
+    ("pa", "ぱ"),
+    ("pi", "ぴ"),
+    ("pu", "ぷ"),
+    ("pe", "ぺ"),
+    ("po", "ぽ"),
+    ("ma", "ま"),
+    ("mi", "み"),
+    ("mu", "む"),
+    ("me", "め"),
+    ("mo", "も"),
+    ("ya", "や"),
+    ("yu", "ゆ"),
+    ("yo", "よ"),
+    ("ra", "ら"),
+    ("ri", "り"),
+    ("ru", "る"),
+    ("re", "れ"),
+    ("ro", "ろ"),
+    ("wa", "わ"),
+    ("wi", "うぃ"),
+    ("we", "うぇ"),
+    ("wo", "を"),
+    ("n", "ん"),
+    ("n'", "ん"),
+    ("kya", "きゃ"),
+    ("kyu", "きゅ"),
+    ("kyo", "きょ"),
+    ("gya", "ぎゃ"),
+    ("gyu", "ぎゅ"),
+    ("gyo", "ぎょ"),
+    ("sya", "しゃ"),
+    ("syu", "しゅ"),
+    ("syo", "しょ"),
+    ("sha", "しゃ"),
+    ("shu", "しゅ"),
+    ("she", "しぇ"),
+    ("sho", "しょ"),
+    ("zya", "じゃ"),
+    ("zyu", "じゅ"),
+    ("zyo", "じょ"),
+    ("ja", "じゃ"),
+    ("ju", "じゅ"),
+    ("je", "じぇ"),
+    ("jo", "じょ"),
+    ("tya", "ちゃ"),
+    ("tyu", "ちゅ"),
+    ("tyo", "ちょ"),
+    ("cha", "ちゃ"),
+    ("chu", "ちゅ"),
+    ("che", "ちぇ"),
+    ("cho", "ちょ"),
+    ("dya", "ぢゃ"),
+    ("dyu", "ぢゅ"),
+    ("dyo", "ぢょ"),
+    ("thi", "てぃ"),
+    ("dhi", "でぃ"),
+    ("nya", "にゃ"),
+    ("nyu", "にゅ"),
+    ("nyo", "にょ"),
+    ("hya", "ひゃ"),
+    ("hyu", "ひゅ"),
+    ("hyo", "ひょ"),
+    ("fa", "ふぁ"),
+    ("fi", "ふぃ"),
+    ("fe", "ふぇ"),
+    ("fo", "ふぉ"),
+    ("bya", "びゃ"),
+    ("byu", "びゅ"),
+    ("byo", "びょ"),
+    ("pya", "ぴゃ"),
+    ("pyu", "ぴゅ"),
+    ("pyo", "ぴょ"),
+    ("mya", "みゃ"),
+    ("myu", "みゅ"),
+    ("myo", "みょ"),
+    ("rya", "りゃ"),
+    ("ryu", "りゅ"),
+    ("ryo", "りょ"),
+    ("vu", "ゔ"),
+    ("xa", "ぁ"),
+    ("xi", "ぃ"),
+    ("xu", "ぅ"),
+    ("xe", "ぇ"),
+    ("xo", "ぉ"),
+    ("xya", "ゃ"),
+    ("xyu", "ゅ"),
+    ("xyo", "ょ"),
+    ("xtu", "っ"),
+    ("xwa", "ゎ"),
+    ("la", "ぁ"),
+    ("li", "ぃ"),
+    ("lu", "ぅ"),
+    ("le", "ぇ"),
+    ("lo", "ぉ"),
+    ("lya", "ゃ"),
+    ("lyu", "ゅ"),
+    ("lyo", "ょ"),
+    ("ltu", "っ"),
+    ("lwa", "ゎ"),
+];
+
+/// Japanese punctuation typed in place of ASCII
+const PUNCTUATION: [(char, char); 6] = [
+    ('-', 'ー'),
+    (',', '、'),
+    ('.', '。'),
+    ('[', '「'),
+    (']', '」'),
+    ('/', '・'),
+];
+
+/// Katakana with half-width forms, in the order of `U+FF66` to `U+FF9D`
+const HALF_WIDTH: &str =
+    "ヲァィゥェォャュョッーアイウエオカキクケコサシスセソタチツテトナニヌネノ\
+     ハヒフヘホマミムメモヤユヨラリルレロワン";
+
+/// Convert a full-width character to half-width
+fn half_width(c: char, out: &mut String) {
+    const VOICED: &str = "ガギグゲゴザジズゼゾダヂヅデドバビブベボ";
+    const SEMI_VOICED: &str = "パピプペポ";
+
+    let (base, mark) = if VOICED.contains(c) {
+        (char::from_u32(u32::from(c) - 1).unwrap(), Some('ﾞ'))
+    } else if SEMI_VOICED.contains(c) {
+        (char::from_u32(u32::from(c) - 2).unwrap(), Some('ﾟ'))
+    } else if c == 'ヴ' {
+        ('ウ', Some('ﾞ'))
+    } else {
+        (c, None)
+    };
+    let base = match base {
+        '。' => '｡',
+        '「' => '｢',
+        '」' => '｣',
+        '、' => '､',
+        '・' => '･',
+        base => HALF_WIDTH
+            .chars()
+            .position(|k| k == base)
+            .and_then(|i| char::from_u32(0xFF66 + i as u32))
+            .unwrap_or(base),
+    };
+
+    out.push(base);
+    out.extend(mark);
+}
+
+/// Romaji being converted to kana
+#[derive(Debug, Default)]
+pub(crate) struct Kana {
+    romaji: String,
+    katakana: bool,
+    half: bool,
+}
+
+impl Kana {
+    /// Create a new input method, typing full-width hiragana
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the romaji typed that hasn't been converted yet
+    pub(crate) fn preedit(&self) -> &str {
+        &self.romaji
+    }
+
+    /// Switch between hiragana and katakana
+    pub(crate) fn toggle_katakana(&mut self) {
+        self.katakana = !self.katakana;
+    }
+
+    /// Switch between full-width and half-width
+    pub(crate) fn toggle_width(&mut self) {
+        self.half = !self.half;
+    }
+
+    /// Convert all remaining romaji, appending the result to `commit`
+    pub(crate) fn flush(&mut self, commit: &mut String) {
+        self.convert(commit, true);
+    }
+
+    /// Type the remaining romaji as-is, without converting it
+    pub(crate) fn flush_romaji(&mut self, commit: &mut String) {
+        let romaji = std::mem::take(&mut self.romaji);
+
+        romaji.chars().for_each(|c| self.emit_ascii(c, commit));
+    }
+
+    /// Remove the last romaji character, returning `false` if there was none
+    pub(crate) fn erase(&mut self) -> bool {
+        self.romaji.pop().is_some()
+    }
+
+    /// Type a character, appending any converted kana to `commit`
+    pub(crate) fn input(&mut self, c: char, commit: &mut String) {
+        let c = c.to_ascii_lowercase();
+
+        if c.is_ascii_lowercase() || (c == '\'' && self.romaji == "n") {
+            self.romaji.push(c);
+            self.convert(commit, false);
+            return;
+        }
+
+        self.flush(commit);
+        match PUNCTUATION.iter().find(|(ascii, _)| *ascii == c) {
+            Some(&(_, kana)) => self.emit_kana(&kana.to_string(), commit),
+            None => self.emit_ascii(c, commit),
+        }
+    }
+
+    fn convert(&mut self, commit: &mut String, flush: bool) {
+        while let Some(first) = self.romaji.chars().next() {
+            let romaji = self.romaji.as_str();
+            let exact = ROMAJI.iter().find(|(r, _)| *r == romaji);
+            let longer = ROMAJI
+                .iter()
+                .any(|(r, _)| r.len() > romaji.len() && r.starts_with(romaji));
+
+            if let Some((_, kana)) = exact.filter(|_| flush || !longer) {
+                self.emit_kana(kana, commit);
+                self.romaji.clear();
+                break;
+            }
+            if longer && !flush {
+                break;
+            }
+
+            let second = self.romaji.chars().nth(1);
+            if second == Some(first) && !"aiueon".contains(first) {
+                // Doubled consonant
+                self.emit_kana("っ", commit);
+            } else if first == 'n' {
+                // "nn" is one ん, unless the second n starts the next kana
+                let third = self.romaji.chars().nth(2);
+                let next = third.map_or(false, |c| "aiueoy".contains(c));
+
+                if second == Some('n') && third.is_none() && !flush {
+                    break;
+                }
+                self.emit_kana("ん", commit);
+                if second == Some('n') && !next {
+                    self.romaji.remove(0);
+                }
+            } else {
+                self.emit_ascii(first, commit);
+            }
+            self.romaji.remove(0);
+        }
+    }
+
+    fn emit_kana(&self, hiragana: &str, commit: &mut String) {
+        for c in hiragana.chars() {
+            let c = match c {
+                'ぁ'..='ゖ' if self.katakana => {
+                    char::from_u32(u32::from(c) + 0x60).unwrap()
+                }
+                c => c,
+            };
+            if self.half {
+                half_width(c, commit);
+            } else {
+                commit.push(c);
+            }
+        }
+    }
+
+    fn emit_ascii(&self, c: char, commit: &mut String) {
+        commit.push(match c {
+            '!'..='~' if !self.half => {
+                char::from_u32(u32::from(c) + 0xFEE0).unwrap()
+            }
+            ' ' if !self.half => '\u{3000}',
+            c => c,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Type romaji, returning the committed text and the romaji left over
+    fn typed(kana: &mut Kana, romaji: &str) -> (String, String) {
+        let mut commit = String::new();
+
+        romaji.chars().for_each(|c| kana.input(c, &mut commit));
+        (commit, kana.preedit().into())
+    }
+
+    #[test]
+    fn hiragana() {
+        let mut kana = Kana::new();
+
+        assert_eq!(
+            typed(&mut kana, "konnichiha"),
+            ("こんにちは".into(), "".into())
+        );
+        assert_eq!(typed(&mut kana, "onna"), ("おんな".into(), "".into()));
+        assert_eq!(typed(&mut kana, "kon'ya"), ("こんや".into(), "".into()));
+        assert_eq!(typed(&mut kana, "konnya"), ("こんにゃ".into(), "".into()));
+        // A lone n waits for the next character
+        assert_eq!(typed(&mut kana, "shinbun"), ("しんぶ".into(), "n".into()));
+
+        let mut commit = String::new();
+
+        kana.flush(&mut commit);
+        assert_eq!(commit, "ん");
+        commit.clear();
+        assert_eq!(typed(&mut kana, "nn"), ("".into(), "nn".into()));
+        kana.flush(&mut commit);
+        assert_eq!(commit, "ん");
+        assert_eq!(typed(&mut kana, "nnk"), ("ん".into(), "k".into()));
+        assert_eq!(typed(&mut kana, "a"), ("か".into(), "".into()));
+    }
+
+    #[test]
+    fn doubled() {
+        let mut kana = Kana::new();
+
+        assert_eq!(typed(&mut kana, "kitte"), ("きって".into(), "".into()));
+        assert_eq!(typed(&mut kana, "zasshi"), ("ざっし".into(), "".into()));
+        assert_eq!(typed(&mut kana, "macchi"), ("まっち".into(), "".into()));
+    }
+
+    #[test]
+    fn katakana() {
+        let mut kana = Kana::new();
+
+        kana.toggle_katakana();
+        assert_eq!(typed(&mut kana, "kon'ya."), ("コンヤ。".into(), "".into()));
+        kana.toggle_width();
+        assert_eq!(typed(&mut kana, "gakkou."), ("ｶﾞｯｺｳ｡".into(), "".into()));
+        kana.toggle_katakana();
+        // Hiragana has no half-width forms
+        assert_eq!(typed(&mut kana, "pa1"), ("ぱ1".into(), "".into()));
+    }
+
+    #[test]
+    fn romaji() {
+        let mut kana = Kana::new();
+        let mut commit = String::new();
+
+        assert_eq!(typed(&mut kana, "kya"), ("きゃ".into(), "".into()));
+        assert_eq!(typed(&mut kana, "ky"), ("".into(), "ky".into()));
+        kana.flush_romaji(&mut commit);
+        assert_eq!(commit, "ｋｙ");
+        commit.clear();
+        kana.toggle_width();
+        typed(&mut kana, "ky");
+        kana.flush_romaji(&mut commit);
+        assert_eq!(commit, "ky");
+    }
+
+    #[test]
+    fn erase() {
+        let mut kana = Kana::new();
+
+        assert_eq!(typed(&mut kana, "sh"), ("".into(), "sh".into()));
+        assert!(kana.erase());
+        assert_eq!(kana.preedit(), "s");
+        assert!(kana.erase());
+        assert_eq!(kana.preedit(), "");
+        assert!(!kana.erase());
+        assert_eq!(typed(&mut kana, "a"), ("あ".into(), "".into()));
+    }
+}
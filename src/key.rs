@@ -818,9 +818,288 @@ pub enum Key {
     NumRight = 255,
 }
 
+impl Key {
+    /// Look up a key by its variant name, as printed by [`Debug`]
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "Grave" => Key::Grave,
+            "Digit1" => Key::Digit1,
+            "Digit2" => Key::Digit2,
+            "Digit3" => Key::Digit3,
+            "Digit4" => Key::Digit4,
+            "Digit5" => Key::Digit5,
+            "Digit6" => Key::Digit6,
+            "Digit7" => Key::Digit7,
+            "Digit8" => Key::Digit8,
+            "Digit9" => Key::Digit9,
+            "Digit0" => Key::Digit0,
+            "Minus" => Key::Minus,
+            "Equals" => Key::Equals,
+            "Backslash" => Key::Backslash,
+            "Tab" => Key::Tab,
+            "Q" => Key::Q,
+            "W" => Key::W,
+            "E" => Key::E,
+            "R" => Key::R,
+            "T" => Key::T,
+            "Y" => Key::Y,
+            "U" => Key::U,
+            "I" => Key::I,
+            "O" => Key::O,
+            "P" => Key::P,
+            "LeftBracket" => Key::LeftBracket,
+            "RightBracket" => Key::RightBracket,
+            "Escape" => Key::Escape,
+            "A" => Key::A,
+            "S" => Key::S,
+            "D" => Key::D,
+            "F" => Key::F,
+            "G" => Key::G,
+            "H" => Key::H,
+            "J" => Key::J,
+            "K" => Key::K,
+            "L" => Key::L,
+            "Semicolon" => Key::Semicolon,
+            "Apostrophe" => Key::Apostrophe,
+            "Enter" => Key::Enter,
+            "LeftShift" => Key::LeftShift,
+            "Z" => Key::Z,
+            "X" => Key::X,
+            "C" => Key::C,
+            "V" => Key::V,
+            "B" => Key::B,
+            "N" => Key::N,
+            "M" => Key::M,
+            "Comma" => Key::Comma,
+            "Period" => Key::Period,
+            "Slash" => Key::Slash,
+            "Up" => Key::Up,
+            "RightShift" => Key::RightShift,
+            "LeftApp" => Key::LeftApp,
+            "LeftPrg" => Key::LeftPrg,
+            "LeftSys" => Key::LeftSys,
+            "Erase" => Key::Erase,
+            "Space" => Key::Space,
+            "Graph" => Key::Graph,
+            "RightPrg" => Key::RightPrg,
+            "RightApp" => Key::RightApp,
+            "Left" => Key::Left,
+            "Down" => Key::Down,
+            "Right" => Key::Right,
+            "Not" => Key::Not,
+            "F1" => Key::F1,
+            "F2" => Key::F2,
+            "F3" => Key::F3,
+            "F4" => Key::F4,
+            "F5" => Key::F5,
+            "F6" => Key::F6,
+            "F7" => Key::F7,
+            "F8" => Key::F8,
+            "F9" => Key::F9,
+            "F10" => Key::F10,
+            "F11" => Key::F11,
+            "F12" => Key::F12,
+            "Power" => Key::Power,
+            "Input" => Key::Input,
+            "F13" => Key::F13,
+            "F14" => Key::F14,
+            "F15" => Key::F15,
+            "F16" => Key::F16,
+            "F17" => Key::F17,
+            "F18" => Key::F18,
+            "F19" => Key::F19,
+            "F20" => Key::F20,
+            "F21" => Key::F21,
+            "F22" => Key::F22,
+            "F23" => Key::F23,
+            "F24" => Key::F24,
+            "MiddleClick" => Key::MiddleClick,
+            "AlphaNumeric" => Key::AlphaNumeric,
+            "Kanji" => Key::Kanji,
+            "Convert" => Key::Convert,
+            "NonConvert" => Key::NonConvert,
+            "Hangul" => Key::Hangul,
+            "Hanja" => Key::Hanja,
+            "Junja" => Key::Junja,
+            "Kana" => Key::Kana,
+            "Final" => Key::Final,
+            "Change" => Key::Change,
+            "Accept" => Key::Accept,
+            "RightClick" => Key::RightClick,
+            "LeftClick" => Key::LeftClick,
+            "Pause" => Key::Pause,
+            "Break" => Key::Break,
+            "Clear" => Key::Clear,
+            "ScrollLock" => Key::ScrollLock,
+            "NumLock" => Key::NumLock,
+            "SysLock" => Key::SysLock,
+            "Menu" => Key::Menu,
+            "Back" => Key::Back,
+            "Forward" => Key::Forward,
+            "RightSys" => Key::RightSys,
+            "PageUp" => Key::PageUp,
+            "DpiClick" => Key::DpiClick,
+            "LeftAppLock" => Key::LeftAppLock,
+            "LeftPrgLock" => Key::LeftPrgLock,
+            "SideClick" => Key::SideClick,
+            "Remove" => Key::Remove,
+            "Compose" => Key::Compose,
+            "Insert" => Key::Insert,
+            "RightPrgLock" => Key::RightPrgLock,
+            "RightAppLock" => Key::RightAppLock,
+            "PageHome" => Key::PageHome,
+            "PageDown" => Key::PageDown,
+            "PageEnd" => Key::PageEnd,
+            "LangPrev" => Key::LangPrev,
+            "LaunchWeb" => Key::LaunchWeb,
+            "LaunchEmail" => Key::LaunchEmail,
+            "LaunchCalculator" => Key::LaunchCalculator,
+            "LaunchFinance" => Key::LaunchFinance,
+            "LaunchPlayer" => Key::LaunchPlayer,
+            "LaunchGame" => Key::LaunchGame,
+            "LaunchCalendar" => Key::LaunchCalendar,
+            "LaunchNotes" => Key::LaunchNotes,
+            "LaunchVideo" => Key::LaunchVideo,
+            "LaunchAudio" => Key::LaunchAudio,
+            "ZoomOut" => Key::ZoomOut,
+            "ZoomIn" => Key::ZoomIn,
+            "ZoomReset" => Key::ZoomReset,
+            "LangNext" => Key::LangNext,
+            "Display" => Key::Display,
+            "WorkspaceUp" => Key::WorkspaceUp,
+            "Dim" => Key::Dim,
+            "Brighten" => Key::Brighten,
+            "LaunchSettings" => Key::LaunchSettings,
+            "LaunchConfigNet" => Key::LaunchConfigNet,
+            "LaunchConfigAV" => Key::LaunchConfigAV,
+            "ScreenCapture" => Key::ScreenCapture,
+            "ScreenCopy" => Key::ScreenCopy,
+            "ScreenShot" => Key::ScreenShot,
+            "ScreenRotateWiddershins" => Key::ScreenRotateWiddershins,
+            "ScreenRotateClockwise" => Key::ScreenRotateClockwise,
+            "MobileSwitchApp" => Key::MobileSwitchApp,
+            "SelectWorkspace" => Key::SelectWorkspace,
+            "WorkspaceDown" => Key::WorkspaceDown,
+            "SelectApp" => Key::SelectApp,
+            "SwitchSpeaker" => Key::SwitchSpeaker,
+            "SwitchMicrophone" => Key::SwitchMicrophone,
+            "MicrophoneMute" => Key::MicrophoneMute,
+            "MicrophoneQuieter" => Key::MicrophoneQuieter,
+            "MicrophoneLouder" => Key::MicrophoneLouder,
+            "Midi" => Key::Midi,
+            "Controller" => Key::Controller,
+            "Phone" => Key::Phone,
+            "NewWindow" => Key::NewWindow,
+            "MobileHomeScreen" => Key::MobileHomeScreen,
+            "SpeakerMute" => Key::SpeakerMute,
+            "SpeakerQuieter" => Key::SpeakerQuieter,
+            "SpeakerLouder" => Key::SpeakerLouder,
+            "Rewind" => Key::Rewind,
+            "StopEject" => Key::StopEject,
+            "FastForward" => Key::FastForward,
+            "Mirror" => Key::Mirror,
+            "WebcamDim" => Key::WebcamDim,
+            "WebcamBrighten" => Key::WebcamBrighten,
+            "Help" => Key::Help,
+            "TileUp" => Key::TileUp,
+            "ScrollUp" => Key::ScrollUp,
+            "ScrollLeft" => Key::ScrollLeft,
+            "ScrollDown" => Key::ScrollDown,
+            "ScrollRight" => Key::ScrollRight,
+            "Prev" => Key::Prev,
+            "Play" => Key::Play,
+            "Next" => Key::Next,
+            "MobileLaunchCall" => Key::MobileLaunchCall,
+            "MobileLaunchCamera" => Key::MobileLaunchCamera,
+            "TileLeft" => Key::TileLeft,
+            "TileDown" => Key::TileDown,
+            "TileRight" => Key::TileRight,
+            "Reserved192" => Key::Reserved192,
+            "Reserved193" => Key::Reserved193,
+            "Reserved194" => Key::Reserved194,
+            "Reserved195" => Key::Reserved195,
+            "Reserved196" => Key::Reserved196,
+            "Reserved197" => Key::Reserved197,
+            "Reserved198" => Key::Reserved198,
+            "Reserved199" => Key::Reserved199,
+            "Reserved200" => Key::Reserved200,
+            "Reserved201" => Key::Reserved201,
+            "Reserved202" => Key::Reserved202,
+            "Reserved203" => Key::Reserved203,
+            "Reserved204" => Key::Reserved204,
+            "Reserved205" => Key::Reserved205,
+            "NumTab" => Key::NumTab,
+            "NumDivide" => Key::NumDivide,
+            "NumMultiply" => Key::NumMultiply,
+            "NumSubtract" => Key::NumSubtract,
+            "NumAdd" => Key::NumAdd,
+            "NumComma" => Key::NumComma,
+            "Reserved212" => Key::Reserved212,
+            "Reserved213" => Key::Reserved213,
+            "Reserved214" => Key::Reserved214,
+            "Reserved215" => Key::Reserved215,
+            "Reserved216" => Key::Reserved216,
+            "Reserved217" => Key::Reserved217,
+            "Reserved218" => Key::Reserved218,
+            "Reserved219" => Key::Reserved219,
+            "Num0" => Key::Num0,
+            "Num1" => Key::Num1,
+            "Num2" => Key::Num2,
+            "Num3" => Key::Num3,
+            "Num4" => Key::Num4,
+            "Num5" => Key::Num5,
+            "Num6" => Key::Num6,
+            "Num7" => Key::Num7,
+            "Num8" => Key::Num8,
+            "Num9" => Key::Num9,
+            "NumDecimalPoint" => Key::NumDecimalPoint,
+            "NumEnter" => Key::NumEnter,
+            "MobileActivityBack" => Key::MobileActivityBack,
+            "Reserved233" => Key::Reserved233,
+            "Reserved234" => Key::Reserved234,
+            "Reserved235" => Key::Reserved235,
+            "Reserved236" => Key::Reserved236,
+            "Reserved237" => Key::Reserved237,
+            "Reserved238" => Key::Reserved238,
+            "Reserved239" => Key::Reserved239,
+            "Reserved240" => Key::Reserved240,
+            "Reserved241" => Key::Reserved241,
+            "Reserved242" => Key::Reserved242,
+            "NumUp" => Key::NumUp,
+            "Reserved244" => Key::Reserved244,
+            "Reserved245" => Key::Reserved245,
+            "Reserved246" => Key::Reserved246,
+            "Reserved247" => Key::Reserved247,
+            "Reserved248" => Key::Reserved248,
+            "Reserved249" => Key::Reserved249,
+            "Reserved250" => Key::Reserved250,
+            "Reserved251" => Key::Reserved251,
+            "Reserved252" => Key::Reserved252,
+            "NumLeft" => Key::NumLeft,
+            "NumDown" => Key::NumDown,
+            "NumRight" => Key::NumRight,
+            _ => return None,
+        })
+    }
+}
+
 impl From<u8> for Key {
     fn from(other: u8) -> Self {
         // Safe because all variants are checked
         unsafe { std::mem::transmute(other) }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_name() {
+        for key in (0..=u8::MAX).map(Key::from) {
+            assert_eq!(Key::from_name(&format!("{:?}", key)), Some(key));
+        }
+        assert_eq!(Key::from_name("a"), None);
+        assert_eq!(Key::from_name(""), None);
+    }
+}
@@ -0,0 +1,615 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use pasts::prelude::*;
+
+use crate::{
+    sequence::{self, Trie, ROOT},
+    Event, Key, Keyboard, Mods, Sequence, Shortcut, ShortcutError, Type,
+};
+
+/// Change to the mode stack of a [`Keymap`]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ModeChange {
+    /// Enter a mode, returning to the current mode when it's popped
+    Push(String),
+    /// Return to the previous mode (the bottom mode is never popped)
+    Pop,
+    /// Replace the current mode
+    Switch(String),
+}
+
+/// Action and mode change bound to a sequence
+#[derive(Clone, Debug)]
+struct Binding<T> {
+    action: Option<T>,
+    change: Option<ModeChange>,
+}
+
+/// Named group of [`Sequence`] bindings in a [`Keymap`]
+///
+/// Strokes that aren't bound fall through to the parent mode, if set.  If
+/// any of the modes insert text, the [`Type::Char`]s and [`Type::Commit`]s
+/// typed (other than by strokes that are bound, or that cancel or continue
+/// a sequence) are sent as [`KeymapEvent::Char`] and [`KeymapEvent::Commit`].
+#[derive(Debug)]
+pub struct Mode<T> {
+    trie: Trie<Binding<T>>,
+    parent: Option<String>,
+    insert: bool,
+    counts: bool,
+}
+
+impl<T> Default for Mode<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Mode<T> {
+    /// Create a new mode with no bindings, which doesn't insert text or
+    /// parse counts
+    pub fn new() -> Self {
+        Self {
+            trie: Trie::new(),
+            parent: None,
+            insert: false,
+            counts: false,
+        }
+    }
+
+    /// Bind an action to a sequence, failing with
+    /// [`ShortcutError::SequenceConflict`] if it conflicts with a sequence
+    /// bound in this mode
+    pub fn bind(
+        &mut self,
+        sequence: Sequence,
+        action: T,
+    ) -> Result<(), ShortcutError> {
+        let binding = Binding {
+            action: Some(action),
+            change: None,
+        };
+
+        self.trie.bind(sequence, binding)
+    }
+
+    /// Bind a mode change (after an optional action) to a sequence, failing
+    /// with [`ShortcutError::SequenceConflict`] if it conflicts with a
+    /// sequence bound in this mode
+    pub fn bind_mode(
+        &mut self,
+        sequence: Sequence,
+        action: Option<T>,
+        change: ModeChange,
+    ) -> Result<(), ShortcutError> {
+        let binding = Binding {
+            action,
+            change: Some(change),
+        };
+
+        self.trie.bind(sequence, binding)
+    }
+
+    /// Remove a sequence's binding, returning `true` if it was bound
+    pub fn unbind(&mut self, sequence: &Sequence) -> bool {
+        self.trie.unbind(sequence).is_some()
+    }
+
+    /// Get the action bound to a sequence
+    pub fn get(&self, sequence: &Sequence) -> Option<&T> {
+        self.trie.get(sequence)?.action.as_ref()
+    }
+
+    /// Set the mode that strokes which aren't bound fall through to
+    pub fn set_parent(&mut self, parent: Option<String>) {
+        self.parent = parent;
+    }
+
+    /// Get the mode that strokes which aren't bound fall through to
+    pub fn parent(&self) -> Option<&str> {
+        self.parent.as_deref()
+    }
+
+    /// Set whether strokes which aren't bound insert the text they type
+    pub fn set_insert(&mut self, insert: bool) {
+        self.insert = insert;
+    }
+
+    /// Check if strokes which aren't bound insert the text they type
+    pub fn insert(&self) -> bool {
+        self.insert
+    }
+
+    /// Set whether digit keys pressed (without modifiers) before a sequence
+    /// are parsed as a count for its action, like `3dw` in vi.  A count
+    /// can't start with `0`, so it can still be bound.
+    pub fn set_counts(&mut self, counts: bool) {
+        self.counts = counts;
+    }
+
+    /// Check if digit keys pressed before a sequence are parsed as a count
+    pub fn counts(&self) -> bool {
+        self.counts
+    }
+}
+
+/// Event from a [`Keymap`]
+#[derive(Clone, Debug)]
+pub enum KeymapEvent<T> {
+    /// A bound sequence was typed, with its action and count (if one was
+    /// typed before it)
+    Action(T, Option<u32>),
+    /// A character was typed in a mode that inserts text
+    Char(char),
+    /// Text was committed (by an input method or compose sequence) in a mode
+    /// that inserts text
+    Commit(String),
+    /// The current mode changed, to the mode with this name
+    Mode(String),
+    /// The start of a bound sequence was typed (to show as `App+X-`)
+    Prefix(Sequence),
+    /// The sequence and count typed so far were cancelled, by pressing
+    /// [`Key::Escape`] or typing a stroke that isn't bound
+    Cancel,
+}
+
+/// Notifier wrapping a [`Keyboard`] that matches [`Sequence`]s bound in a
+/// stack of named [`Mode`]s, yielding [`KeymapEvent`]s
+///
+/// Strokes are matched against the mode at the top of the stack.  Pressing
+/// a modifier key doesn't count as a stroke, and other events from the
+/// keyboard are discarded.  Modes that haven't been added have no bindings.
+#[derive(Debug)]
+pub struct Keymap<T> {
+    keyboard: Keyboard,
+    modes: BTreeMap<String, Mode<T>>,
+    stack: Vec<String>,
+    /// Mode the sequence typed so far was started in, and its current node
+    at: Option<(String, usize)>,
+    prefix: Vec<Shortcut>,
+    count: Option<u32>,
+    /// Key held down that was used by a stroke, so the text it types isn't
+    /// inserted
+    swallowed: Option<Key>,
+    pending: VecDeque<KeymapEvent<T>>,
+}
+
+impl<T> Keymap<T> {
+    /// Create a new keymap with no modes, starting in the named mode
+    pub fn new(keyboard: Keyboard, mode: &str) -> Self {
+        Self {
+            keyboard,
+            modes: BTreeMap::new(),
+            stack: vec![mode.to_string()],
+            at: None,
+            prefix: Vec::new(),
+            count: None,
+            swallowed: None,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Add a mode, replacing and returning any previous mode with its name
+    pub fn insert_mode(
+        &mut self,
+        name: &str,
+        mode: Mode<T>,
+    ) -> Option<Mode<T>> {
+        self.modes.insert(name.to_string(), mode)
+    }
+
+    /// Remove a mode, returning it
+    pub fn remove_mode(&mut self, name: &str) -> Option<Mode<T>> {
+        self.modes.remove(name)
+    }
+
+    /// Get a mode by name
+    pub fn get_mode(&self, name: &str) -> Option<&Mode<T>> {
+        self.modes.get(name)
+    }
+
+    /// Get a mode by name mutably, to change its bindings
+    pub fn get_mode_mut(&mut self, name: &str) -> Option<&mut Mode<T>> {
+        self.modes.get_mut(name)
+    }
+
+    /// Get the name of the current mode
+    pub fn mode(&self) -> &str {
+        self.stack.last().unwrap()
+    }
+
+    /// Get the names of the modes on the stack, from the bottom up
+    pub fn stack(&self) -> &[String] {
+        &self.stack
+    }
+
+    /// Change the mode stack, sending a [`KeymapEvent::Mode`] if the current
+    /// mode changed.  This cancels the sequence and count typed so far.
+    pub fn change_mode(&mut self, change: ModeChange) {
+        let before = self.mode().to_string();
+
+        match change {
+            ModeChange::Push(mode) => self.stack.push(mode),
+            ModeChange::Pop if self.stack.len() > 1 => {
+                self.stack.pop();
+            }
+            ModeChange::Pop => {}
+            ModeChange::Switch(mode) => *self.stack.last_mut().unwrap() = mode,
+        }
+        self.reset();
+        if self.mode() != before {
+            self.pending
+                .push_back(KeymapEvent::Mode(self.mode().into()));
+        }
+    }
+
+    /// Get the count typed so far
+    pub fn count(&self) -> Option<u32> {
+        self.count
+    }
+
+    /// Get the start of a bound sequence typed so far (empty if none)
+    pub fn prefix(&self) -> Sequence {
+        Sequence::new(self.prefix.iter().copied())
+    }
+
+    /// Get the wrapped keyboard
+    pub fn keyboard(&self) -> &Keyboard {
+        &self.keyboard
+    }
+
+    /// Get the wrapped keyboard mutably, to configure it
+    pub fn keyboard_mut(&mut self) -> &mut Keyboard {
+        &mut self.keyboard
+    }
+
+    /// Get the names of the current mode followed by the modes it falls
+    /// through to
+    fn chain(&self) -> Vec<String> {
+        let mut chain = Vec::new();
+        let mut name = self.mode();
+
+        while let Some(mode) = self.modes.get(name) {
+            // Stop if the parents form a loop
+            if chain.iter().any(|n| n == name) {
+                break;
+            }
+            chain.push(name.to_string());
+            match mode.parent() {
+                Some(parent) => name = parent,
+                None => break,
+            }
+        }
+        chain
+    }
+
+    /// Check if the text typed now is inserted
+    fn inserting(&self) -> bool {
+        self.swallowed.is_none()
+            && self.chain().iter().any(|name| self.modes[name].insert)
+    }
+
+    /// Cancel the sequence and count typed so far, returning `true` if there
+    /// was one
+    fn reset(&mut self) -> bool {
+        let typed = !self.prefix.is_empty() || self.count.is_some();
+
+        self.at = None;
+        self.prefix.clear();
+        self.count = None;
+        typed
+    }
+}
+
+impl<T: Clone> Keymap<T> {
+    /// Match a key press or repeat against the bindings
+    fn stroke(&mut self, key: Key, mods: Mods, device: Option<u32>) {
+        if sequence::modifier(key) {
+            return;
+        }
+        self.swallowed = Some(key);
+        if key == Key::Escape && self.reset() {
+            self.pending.push_back(KeymapEvent::Cancel);
+            return;
+        }
+
+        let stroke = Shortcut::pressed(&self.keyboard, key, mods, device);
+        let found = match self.at.take() {
+            Some((name, at)) => self
+                .modes
+                .get(&name)
+                .and_then(|mode| mode.trie.next(at, stroke))
+                .map(|next| (name, next)),
+            None => {
+                let chain = self.chain();
+                if self.count_digit(&chain, stroke) {
+                    return;
+                }
+                let found = chain.into_iter().find_map(|name| {
+                    let next = self.modes[&name].trie.next(ROOT, stroke);
+                    next.map(|next| (name, next))
+                });
+                if found.is_none() {
+                    self.swallowed = None;
+                }
+                found
+            }
+        };
+        let (name, next) = match found {
+            Some(found) => found,
+            None => {
+                if self.reset() {
+                    self.pending.push_back(KeymapEvent::Cancel);
+                }
+                return;
+            }
+        };
+
+        let binding = match self.modes[&name].trie.action(next) {
+            Some(binding) => binding.clone(),
+            None => {
+                self.at = Some((name, next));
+                self.prefix.push(stroke);
+                self.pending.push_back(KeymapEvent::Prefix(self.prefix()));
+                return;
+            }
+        };
+        let count = self.count;
+        self.reset();
+        if let Some(action) = binding.action {
+            self.pending.push_back(KeymapEvent::Action(action, count));
+        }
+        if let Some(change) = binding.change {
+            self.change_mode(change);
+        }
+    }
+
+    /// Handle an event from the keyboard
+    fn event(&mut self, event: Event) {
+        match event.typed {
+            Type::Press(key, mods) | Type::Repeat(key, mods) => {
+                self.stroke(key, mods, event.device)
+            }
+            Type::Release(key, _) if self.swallowed == Some(key) => {
+                self.swallowed = None;
+            }
+            Type::Char(c) if self.inserting() => {
+                self.pending.push_back(KeymapEvent::Char(c));
+            }
+            Type::Commit(text) if self.inserting() => {
+                self.pending.push_back(KeymapEvent::Commit(text));
+            }
+            _ => {}
+        }
+    }
+
+    /// Add a stroke to the count if it's a digit that continues one, or
+    /// starts one in a mode that parses counts, returning `true` if it did
+    fn count_digit(&mut self, chain: &[String], stroke: Shortcut) -> bool {
+        let counts =
+            chain.first().map_or(false, |name| self.modes[name].counts);
+        let digit = match digit(stroke.key()) {
+            Some(digit) if counts && stroke.mods().0 == 0 => digit,
+            _ => return false,
+        };
+
+        if digit == 0 && self.count.is_none() {
+            return false;
+        }
+        let count = self.count.unwrap_or(0).saturating_mul(10);
+        self.count = Some(count.saturating_add(digit));
+        true
+    }
+}
+
+impl<T: Clone + Unpin> Notifier for Keymap<T> {
+    type Event = KeymapEvent<T>;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        exec: &mut Exec<'_>,
+    ) -> Poll<Self::Event> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Ready(event);
+            }
+            match self.keyboard.poll_event(exec) {
+                Ready(event) => self.event(event),
+                Pending => return Pending,
+            }
+        }
+    }
+}
+
+/// Get the digit typed by a digit or numpad digit key
+fn digit(key: Key) -> Option<u32> {
+    let digits = Key::Digit1 as u8..=Key::Digit9 as u8;
+    let numpad = Key::Num0 as u8..=Key::Num9 as u8;
+    let code = key as u8;
+
+    if key == Key::Digit0 {
+        Some(0)
+    } else if digits.contains(&code) {
+        Some(u32::from(code - Key::Digit1 as u8 + 1))
+    } else if numpad.contains(&code) {
+        Some(u32::from(code - Key::Num0 as u8))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pasts::Executor;
+
+    use super::*;
+    use crate::{Layout, Message, Sent};
+
+    /// Keymap with an insert mode binding `App+X App+S`, `F1` and `A`
+    fn keymap() -> Keymap<&'static str> {
+        let mut keymap = Keymap::new(Keyboard::new(), "insert");
+        let mut mode = Mode::new();
+
+        mode.set_insert(true);
+        mode.bind("App+X App+S".parse().unwrap(), "save").unwrap();
+        mode.bind("F1".parse().unwrap(), "help").unwrap();
+        mode.bind("A".parse().unwrap(), "a").unwrap();
+        keymap.insert_mode("insert", mode);
+        keymap
+    }
+
+    /// Press and release keys on a keymap's keyboard
+    fn tap(keymap: &mut Keymap<&str>, keys: &[Key]) {
+        for key in keys {
+            let none = Mods::new();
+            for typed in [Type::Press(*key, none), Type::Release(*key, none)] {
+                let sent = Sent(Message::Type(typed.into()), None, None);
+                keymap.keyboard.receive(sent);
+            }
+        }
+    }
+
+    /// Get the next keymap event
+    async fn next(keymap: &mut Keymap<&str>) -> String {
+        format!("{:?}", keymap.next().await)
+    }
+
+    /// Handle events, returning the keymap events
+    fn events(keymap: &mut Keymap<&str>, typed: Vec<Type>) -> Vec<String> {
+        for typed in typed {
+            keymap.event(Event::new(typed));
+        }
+        keymap
+            .pending
+            .drain(..)
+            .map(|e| format!("{:?}", e))
+            .collect()
+    }
+
+    #[test]
+    fn insert() {
+        let mut keymap = keymap();
+        let none = Mods::new();
+
+        // Bound strokes don't insert the text they type
+        assert_eq!(
+            events(
+                &mut keymap,
+                vec![
+                    Type::Press(Key::A, none),
+                    Type::Char('a'),
+                    Type::Release(Key::A, none),
+                    Type::Press(Key::B, none),
+                    Type::Char('b'),
+                    Type::Release(Key::B, none),
+                ],
+            ),
+            ["Action(\"a\", None)", "Char('b')"],
+        );
+        // Text without a key press, such as from a terminal or input method
+        assert_eq!(
+            events(
+                &mut keymap,
+                vec![Type::Char('c'), Type::Commit("한".to_string())],
+            ),
+            ["Char('c')", "Commit(\"한\")"],
+        );
+    }
+
+    #[test]
+    fn sequences() {
+        let mut keymap = keymap();
+        let mut app = Mods::new();
+
+        app.set_app(true);
+        assert_eq!(
+            events(
+                &mut keymap,
+                vec![
+                    Type::Press(Key::X, app),
+                    Type::Release(Key::X, app),
+                    Type::Press(Key::S, app),
+                    Type::Release(Key::S, app),
+                    Type::Press(Key::X, app),
+                    Type::Press(Key::Escape, Mods::new()),
+                    Type::Char('\u{1b}'),
+                    Type::Release(Key::Escape, Mods::new()),
+                    Type::Char('d'),
+                ],
+            ),
+            [
+                "Prefix(Sequence([Shortcut { key: X, mods: 32 }]))",
+                "Action(\"save\", None)",
+                "Prefix(Sequence([Shortcut { key: X, mods: 32 }]))",
+                "Cancel",
+                "Char('d')",
+            ],
+        );
+    }
+
+    #[test]
+    fn modes() {
+        let mut keymap = Keymap::new(Keyboard::new(), "normal");
+        let (mut normal, mut insert, mut visual) =
+            (Mode::new(), Mode::new(), Mode::new());
+        let push = ModeChange::Push("insert".into());
+        let switch = ModeChange::Switch("visual".into());
+
+        normal.set_counts(true);
+        normal.bind("D W".parse().unwrap(), "delete").unwrap();
+        normal.bind_mode("I".parse().unwrap(), None, push).unwrap();
+        normal
+            .bind_mode("V".parse().unwrap(), None, switch)
+            .unwrap();
+        insert.set_insert(true);
+        insert
+            .bind_mode("Escape".parse().unwrap(), None, ModeChange::Pop)
+            .unwrap();
+        visual.set_parent(Some("normal".into()));
+        visual.bind("Y".parse().unwrap(), "yank").unwrap();
+        keymap.keyboard_mut().set_layout(Some(Layout::qwerty()));
+        keymap.insert_mode("normal", normal);
+        keymap.insert_mode("insert", insert);
+        keymap.insert_mode("visual", visual);
+
+        Executor::default().spawn(async move {
+            // Count prefix
+            tap(&mut keymap, &[Key::Digit3, Key::D]);
+            assert_eq!(
+                next(&mut keymap).await,
+                "Prefix(Sequence([Shortcut { key: D, mods: 0 }]))",
+            );
+            assert_eq!(keymap.count(), Some(3));
+            tap(&mut keymap, &[Key::W]);
+            assert_eq!(next(&mut keymap).await, "Action(\"delete\", Some(3))");
+            assert_eq!(keymap.count(), None);
+
+            // Push and pop
+            tap(&mut keymap, &[Key::I, Key::B]);
+            assert_eq!(next(&mut keymap).await, "Mode(\"insert\")");
+            assert_eq!(next(&mut keymap).await, "Char('b')");
+            assert_eq!(keymap.stack(), ["normal", "insert"]);
+            tap(&mut keymap, &[Key::Escape]);
+            assert_eq!(next(&mut keymap).await, "Mode(\"normal\")");
+            assert_eq!(keymap.stack(), ["normal"]);
+
+            // Switch, falling through to the parent mode
+            tap(&mut keymap, &[Key::V, Key::Y, Key::D, Key::W]);
+            assert_eq!(next(&mut keymap).await, "Mode(\"visual\")");
+            assert_eq!(keymap.stack(), ["visual"]);
+            assert_eq!(next(&mut keymap).await, "Action(\"yank\", None)");
+            assert_eq!(
+                next(&mut keymap).await,
+                "Prefix(Sequence([Shortcut { key: D, mods: 0 }]))",
+            );
+            assert_eq!(next(&mut keymap).await, "Action(\"delete\", None)");
+            // Counts are only parsed if the current mode parses them
+            tap(&mut keymap, &[Key::Digit3, Key::D, Key::W]);
+            assert_eq!(
+                next(&mut keymap).await,
+                "Prefix(Sequence([Shortcut { key: D, mods: 0 }]))",
+            );
+            assert_eq!(next(&mut keymap).await, "Action(\"delete\", None)");
+        });
+    }
+}
@@ -0,0 +1,176 @@
+use std::fmt;
+
+use crate::Key;
+
+/// Set of [`Key`]s, stored as a 256-bit bitset
+#[derive(Copy, Clone, Default, Eq, Hash, PartialEq)]
+pub struct KeySet([u64; 4]);
+
+impl fmt::Debug for KeySet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl KeySet {
+    /// Create an empty set
+    pub const fn new() -> Self {
+        Self([0; 4])
+    }
+
+    /// Split a key into its word index and bit mask
+    fn bit(key: Key) -> (usize, u64) {
+        let key = key as u8;
+
+        (usize::from(key / 64), 1 << (key % 64))
+    }
+
+    /// Add a key, returning `false` if it was already in the set
+    pub fn insert(&mut self, key: Key) -> bool {
+        let (lvl, bit) = Self::bit(key);
+        let inserted = self.0[lvl] & bit == 0;
+
+        self.0[lvl] |= bit;
+        inserted
+    }
+
+    /// Remove a key, returning `false` if it wasn't in the set
+    pub fn remove(&mut self, key: Key) -> bool {
+        let (lvl, bit) = Self::bit(key);
+        let removed = self.0[lvl] & bit != 0;
+
+        self.0[lvl] &= !bit;
+        removed
+    }
+
+    /// Check if a key is in the set
+    pub fn contains(&self, key: Key) -> bool {
+        let (lvl, bit) = Self::bit(key);
+
+        self.0[lvl] & bit != 0
+    }
+
+    /// Remove all keys
+    pub fn clear(&mut self) {
+        self.0 = [0; 4];
+    }
+
+    /// Get the number of keys in the set
+    pub fn len(&self) -> usize {
+        self.0.iter().map(|word| word.count_ones() as usize).sum()
+    }
+
+    /// Check if the set is empty
+    pub fn is_empty(&self) -> bool {
+        self.0 == [0; 4]
+    }
+
+    /// Iterate over the keys in the set, in order of their values
+    pub fn iter(&self) -> impl Iterator<Item = Key> + '_ {
+        (0..=u8::MAX)
+            .map(Key::from)
+            .filter(move |key| self.contains(*key))
+    }
+
+    /// Get the keys in either set
+    pub fn union(&self, other: &Self) -> Self {
+        let mut set = *self;
+        set.0.iter_mut().zip(other.0).for_each(|(a, b)| *a |= b);
+        set
+    }
+
+    /// Get the keys in both sets
+    pub fn intersection(&self, other: &Self) -> Self {
+        let mut set = *self;
+        set.0.iter_mut().zip(other.0).for_each(|(a, b)| *a &= b);
+        set
+    }
+
+    /// Get the keys in this set that aren't in the other set
+    pub fn difference(&self, other: &Self) -> Self {
+        let mut set = *self;
+        set.0.iter_mut().zip(other.0).for_each(|(a, b)| *a &= !b);
+        set
+    }
+}
+
+impl Extend<Key> for KeySet {
+    fn extend<I: IntoIterator<Item = Key>>(&mut self, keys: I) {
+        for key in keys {
+            self.insert(key);
+        }
+    }
+}
+
+impl FromIterator<Key> for KeySet {
+    fn from_iter<I: IntoIterator<Item = Key>>(keys: I) -> Self {
+        let mut set = Self::new();
+        set.extend(keys);
+        set
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Keys on either side of each word boundary
+    const EDGES: [u8; 8] = [0, 63, 64, 127, 128, 191, 192, 255];
+
+    #[test]
+    fn insert_remove() {
+        let mut set = KeySet::new();
+
+        for key in EDGES.map(Key::from) {
+            assert!(set.insert(key));
+            assert!(!set.insert(key));
+            assert!(set.contains(key));
+        }
+        assert_eq!(set.len(), EDGES.len());
+        assert_eq!(set.iter().map(|key| key as u8).collect::<Vec<_>>(), EDGES);
+        // Neighbours of the edges aren't set
+        for key in [1, 62, 65, 126, 129, 190, 193, 254].map(Key::from) {
+            assert!(!set.contains(key));
+        }
+
+        for key in EDGES.map(Key::from) {
+            assert!(set.remove(key));
+            assert!(!set.remove(key));
+            assert!(!set.contains(key));
+        }
+        assert!(set.is_empty());
+        assert_eq!(set.iter().next(), None);
+    }
+
+    #[test]
+    fn set_operations() {
+        let a = [Key::Grave, Key::Right, Key::Not, Key::NumRight]
+            .into_iter()
+            .collect::<KeySet>();
+        let b = [Key::Right, Key::Not, Key::PageEnd, Key::LangPrev]
+            .into_iter()
+            .collect::<KeySet>();
+
+        assert_eq!(
+            a.union(&b).iter().collect::<Vec<_>>(),
+            [
+                Key::Grave,
+                Key::Right,
+                Key::Not,
+                Key::PageEnd,
+                Key::LangPrev,
+                Key::NumRight,
+            ],
+        );
+        assert_eq!(
+            a.intersection(&b).iter().collect::<Vec<_>>(),
+            [Key::Right, Key::Not],
+        );
+        assert_eq!(
+            a.difference(&b).iter().collect::<Vec<_>>(),
+            [Key::Grave, Key::NumRight],
+        );
+        assert!(a.intersection(&KeySet::new()).is_empty());
+        assert_eq!(a.union(&KeySet::new()), a);
+    }
+}
@@ -0,0 +1,855 @@
+use std::{fmt, str::FromStr};
+
+use crate::{dead::is_dead, Key, Mods};
+
+/// US QWERTY, matching the legends documented on [`Key`]
+const QWERTY: &[(Key, &str)] = &[
+    (Key::Grave, "`~"),
+    (Key::Digit1, "1!"),
+    (Key::Digit2, "2@"),
+    (Key::Digit3, "3#"),
+    (Key::Digit4, "4$"),
+    (Key::Digit5, "5%"),
+    (Key::Digit6, "6^"),
+    (Key::Digit7, "7&"),
+    (Key::Digit8, "8*"),
+    (Key::Digit9, "9("),
+    (Key::Digit0, "0)"),
+    (Key::Minus, "-_"),
+    (Key::Equals, "=+"),
+    (Key::Backslash, "\\|"),
+    (Key::Q, "qQ"),
+    (Key::W, "wW"),
+    (Key::E, "eE"),
+    (Key::R, "rR"),
+    (Key::T, "tT"),
+    (Key::Y, "yY"),
+    (Key::U, "uU"),
+    (Key::I, "iI"),
+    (Key::O, "oO"),
+    (Key::P, "pP"),
+    (Key::LeftBracket, "[{"),
+    (Key::RightBracket, "]}"),
+    (Key::A, "aA"),
+    (Key::S, "sS"),
+    (Key::D, "dD"),
+    (Key::F, "fF"),
+    (Key::G, "gG"),
+    (Key::H, "hH"),
+    (Key::J, "jJ"),
+    (Key::K, "kK"),
+    (Key::L, "lL"),
+    (Key::Semicolon, ";:"),
+    (Key::Apostrophe, "'\""),
+    (Key::Z, "zZ"),
+    (Key::X, "xX"),
+    (Key::C, "cC"),
+    (Key::V, "vV"),
+    (Key::B, "bB"),
+    (Key::N, "nN"),
+    (Key::M, "mM"),
+    (Key::Comma, ",<"),
+    (Key::Period, ".>"),
+    (Key::Slash, "/?"),
+];
+
+/// US Dvorak
+const DVORAK: &[(Key, &str)] = &[
+    (Key::Grave, "`~"),
+    (Key::Digit1, "1!"),
+    (Key::Digit2, "2@"),
+    (Key::Digit3, "3#"),
+    (Key::Digit4, "4$"),
+    (Key::Digit5, "5%"),
+    (Key::Digit6, "6^"),
+    (Key::Digit7, "7&"),
+    (Key::Digit8, "8*"),
+    (Key::Digit9, "9("),
+    (Key::Digit0, "0)"),
+    (Key::Minus, "[{"),
+    (Key::Equals, "]}"),
+    (Key::Backslash, "\\|"),
+    (Key::Q, "'\""),
+    (Key::W, ",<"),
+    (Key::E, ".>"),
+    (Key::R, "pP"),
+    (Key::T, "yY"),
+    (Key::Y, "fF"),
+    (Key::U, "gG"),
+    (Key::I, "cC"),
+    (Key::O, "rR"),
+    (Key::P, "lL"),
+    (Key::LeftBracket, "/?"),
+    (Key::RightBracket, "=+"),
+    (Key::A, "aA"),
+    (Key::S, "oO"),
+    (Key::D, "eE"),
+    (Key::F, "uU"),
+    (Key::G, "iI"),
+    (Key::H, "dD"),
+    (Key::J, "hH"),
+    (Key::K, "tT"),
+    (Key::L, "nN"),
+    (Key::Semicolon, "sS"),
+    (Key::Apostrophe, "-_"),
+    (Key::Z, ";:"),
+    (Key::X, "qQ"),
+    (Key::C, "jJ"),
+    (Key::V, "kK"),
+    (Key::B, "xX"),
+    (Key::N, "bB"),
+    (Key::M, "mM"),
+    (Key::Comma, "wW"),
+    (Key::Period, "vV"),
+    (Key::Slash, "zZ"),
+];
+
+/// Colemak
+const COLEMAK: &[(Key, &str)] = &[
+    (Key::Grave, "`~"),
+    (Key::Digit1, "1!"),
+    (Key::Digit2, "2@"),
+    (Key::Digit3, "3#"),
+    (Key::Digit4, "4$"),
+    (Key::Digit5, "5%"),
+    (Key::Digit6, "6^"),
+    (Key::Digit7, "7&"),
+    (Key::Digit8, "8*"),
+    (Key::Digit9, "9("),
+    (Key::Digit0, "0)"),
+    (Key::Minus, "-_"),
+    (Key::Equals, "=+"),
+    (Key::Backslash, "\\|"),
+    (Key::Q, "qQ"),
+    (Key::W, "wW"),
+    (Key::E, "fF"),
+    (Key::R, "pP"),
+    (Key::T, "gG"),
+    (Key::Y, "jJ"),
+    (Key::U, "lL"),
+    (Key::I, "uU"),
+    (Key::O, "yY"),
+    (Key::P, ";:"),
+    (Key::LeftBracket, "[{"),
+    (Key::RightBracket, "]}"),
+    (Key::A, "aA"),
+    (Key::S, "rR"),
+    (Key::D, "sS"),
+    (Key::F, "tT"),
+    (Key::G, "dD"),
+    (Key::H, "hH"),
+    (Key::J, "nN"),
+    (Key::K, "eE"),
+    (Key::L, "iI"),
+    (Key::Semicolon, "oO"),
+    (Key::Apostrophe, "'\""),
+    (Key::Z, "zZ"),
+    (Key::X, "xX"),
+    (Key::C, "cC"),
+    (Key::V, "vV"),
+    (Key::B, "bB"),
+    (Key::N, "kK"),
+    (Key::M, "mM"),
+    (Key::Comma, ",<"),
+    (Key::Period, ".>"),
+    (Key::Slash, "/?"),
+];
+
+/// French AZERTY
+const AZERTY: &[(Key, &str)] = &[
+    (Key::Grave, "²"),
+    (Key::Digit1, "&1"),
+    (Key::Digit2, "é2~"),
+    (Key::Digit3, "\"3#"),
+    (Key::Digit4, "'4{"),
+    (Key::Digit5, "(5["),
+    (Key::Digit6, "-6|"),
+    (Key::Digit7, "è7`"),
+    (Key::Digit8, "_8\\"),
+    (Key::Digit9, "ç9^"),
+    (Key::Digit0, "à0@"),
+    (Key::Minus, ")°]"),
+    (Key::Equals, "=+}"),
+    (Key::Backslash, "*µ"),
+    (Key::Q, "aA"),
+    (Key::W, "zZ"),
+    (Key::E, "eE€"),
+    (Key::R, "rR"),
+    (Key::T, "tT"),
+    (Key::Y, "yY"),
+    (Key::U, "uU"),
+    (Key::I, "iI"),
+    (Key::O, "oO"),
+    (Key::P, "pP"),
+    (Key::LeftBracket, "\u{302}\u{308}"),
+    (Key::RightBracket, "$£¤"),
+    (Key::A, "qQ"),
+    (Key::S, "sS"),
+    (Key::D, "dD"),
+    (Key::F, "fF"),
+    (Key::G, "gG"),
+    (Key::H, "hH"),
+    (Key::J, "jJ"),
+    (Key::K, "kK"),
+    (Key::L, "lL"),
+    (Key::Semicolon, "mM"),
+    (Key::Apostrophe, "ù%"),
+    (Key::Z, "wW"),
+    (Key::X, "xX"),
+    (Key::C, "cC"),
+    (Key::V, "vV"),
+    (Key::B, "bB"),
+    (Key::N, "nN"),
+    (Key::M, ",?"),
+    (Key::Comma, ";."),
+    (Key::Period, ":/"),
+    (Key::Slash, "!§"),
+];
+
+/// German QWERTZ
+const QWERTZ: &[(Key, &str)] = &[
+    (Key::Grave, "\u{302}°"),
+    (Key::Digit1, "1!"),
+    (Key::Digit2, "2\"²"),
+    (Key::Digit3, "3§³"),
+    (Key::Digit4, "4$"),
+    (Key::Digit5, "5%"),
+    (Key::Digit6, "6&"),
+    (Key::Digit7, "7/{"),
+    (Key::Digit8, "8(["),
+    (Key::Digit9, "9)]"),
+    (Key::Digit0, "0=}"),
+    (Key::Minus, "ß?\\"),
+    (Key::Equals, "\u{301}\u{300}"),
+    (Key::Backslash, "#'"),
+    (Key::Q, "qQ@"),
+    (Key::W, "wW"),
+    (Key::E, "eE€"),
+    (Key::R, "rR"),
+    (Key::T, "tT"),
+    (Key::Y, "zZ"),
+    (Key::U, "uU"),
+    (Key::I, "iI"),
+    (Key::O, "oO"),
+    (Key::P, "pP"),
+    (Key::LeftBracket, "üÜ"),
+    (Key::RightBracket, "+*~"),
+    (Key::A, "aA"),
+    (Key::S, "sS"),
+    (Key::D, "dD"),
+    (Key::F, "fF"),
+    (Key::G, "gG"),
+    (Key::H, "hH"),
+    (Key::J, "jJ"),
+    (Key::K, "kK"),
+    (Key::L, "lL"),
+    (Key::Semicolon, "öÖ"),
+    (Key::Apostrophe, "äÄ"),
+    (Key::Z, "yY"),
+    (Key::X, "xX"),
+    (Key::C, "cC"),
+    (Key::V, "vV"),
+    (Key::B, "bB"),
+    (Key::N, "nN"),
+    (Key::M, "mMµ"),
+    (Key::Comma, ",;"),
+    (Key::Period, ".:"),
+    (Key::Slash, "-_"),
+];
+
+/// Japanese JIS (romaji legends; the key left of `1` is Hankaku/Zenkaku)
+const JIS: &[(Key, &str)] = &[
+    (Key::Digit1, "1!"),
+    (Key::Digit2, "2\""),
+    (Key::Digit3, "3#"),
+    (Key::Digit4, "4$"),
+    (Key::Digit5, "5%"),
+    (Key::Digit6, "6&"),
+    (Key::Digit7, "7'"),
+    (Key::Digit8, "8("),
+    (Key::Digit9, "9)"),
+    (Key::Digit0, "0"),
+    (Key::Minus, "-="),
+    (Key::Equals, "^~"),
+    (Key::Backslash, "]}"),
+    (Key::Q, "qQ"),
+    (Key::W, "wW"),
+    (Key::E, "eE"),
+    (Key::R, "rR"),
+    (Key::T, "tT"),
+    (Key::Y, "yY"),
+    (Key::U, "uU"),
+    (Key::I, "iI"),
+    (Key::O, "oO"),
+    (Key::P, "pP"),
+    (Key::LeftBracket, "@`"),
+    (Key::RightBracket, "[{"),
+    (Key::A, "aA"),
+    (Key::S, "sS"),
+    (Key::D, "dD"),
+    (Key::F, "fF"),
+    (Key::G, "gG"),
+    (Key::H, "hH"),
+    (Key::J, "jJ"),
+    (Key::K, "kK"),
+    (Key::L, "lL"),
+    (Key::Semicolon, ";+"),
+    (Key::Apostrophe, ":*"),
+    (Key::Z, "zZ"),
+    (Key::X, "xX"),
+    (Key::C, "cC"),
+    (Key::V, "vV"),
+    (Key::B, "bB"),
+    (Key::N, "nN"),
+    (Key::M, "mM"),
+    (Key::Comma, ",<"),
+    (Key::Period, ".>"),
+    (Key::Slash, "/?"),
+];
+
+/// Keys that type the same characters on every layout
+const COMMON: &[(Key, &str)] = &[
+    (Key::Space, "  "),
+    (Key::NumDivide, "//"),
+    (Key::NumMultiply, "**"),
+    (Key::NumSubtract, "--"),
+    (Key::NumAdd, "++"),
+    (Key::NumComma, ",,"),
+    (Key::Num0, "00"),
+    (Key::Num1, "11"),
+    (Key::Num2, "22"),
+    (Key::Num3, "33"),
+    (Key::Num4, "44"),
+    (Key::Num5, "55"),
+    (Key::Num6, "66"),
+    (Key::Num7, "77"),
+    (Key::Num8, "88"),
+    (Key::Num9, "99"),
+    (Key::NumDecimalPoint, ".."),
+];
+
+/// Modifier level of a key in a [`Layout`]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Level {
+    /// No modifiers
+    Base = 0,
+    /// Shift
+    Shift = 1,
+    /// Graph (AltGr)
+    Graph = 2,
+    /// Shift + Graph (AltGr)
+    ShiftGraph = 3,
+}
+
+impl Level {
+    /// All levels, in order
+    pub const ALL: [Level; 4] =
+        [Level::Base, Level::Shift, Level::Graph, Level::ShiftGraph];
+
+    /// Get the level selected by a modifier state
+    pub fn from_mods(mods: Mods) -> Self {
+        Self::ALL[usize::from(mods.shift()) | usize::from(mods.graph()) << 1]
+    }
+}
+
+/// Kind of error found while parsing a [`Layout`]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum LayoutErrorKind {
+    /// Not the name of a [`Key`]
+    UnknownKey(String),
+    /// The key was already listed on a previous line
+    DuplicateKey(Key),
+    /// Not a single character, `-` or `U+` code point
+    InvalidEntry(String),
+    /// More than four levels listed for a key
+    TooManyLevels,
+}
+
+/// Error parsing a [`Layout`] from its text format
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LayoutError {
+    /// Line number, starting at 1
+    pub line: usize,
+    /// Column number (in characters), starting at 1
+    pub column: usize,
+    /// What went wrong
+    pub kind: LayoutErrorKind,
+}
+
+impl fmt::Display for LayoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: ", self.line, self.column)?;
+        match &self.kind {
+            LayoutErrorKind::UnknownKey(name) => {
+                write!(f, "unknown key `{}`", name)
+            }
+            LayoutErrorKind::DuplicateKey(key) => {
+                write!(f, "duplicate key `{:?}`", key)
+            }
+            LayoutErrorKind::InvalidEntry(entry) => {
+                write!(f, "invalid entry `{}`", entry)
+            }
+            LayoutErrorKind::TooManyLevels => write!(f, "more than 4 levels"),
+        }
+    }
+}
+
+impl std::error::Error for LayoutError {}
+
+/// Keyboard layout, mapping [`Key`]s at each modifier [`Level`] to the
+/// characters they type
+///
+/// # Text Format
+/// Layouts can be loaded with [`str::parse()`] and saved with
+/// [`ToString::to_string()`].  Each line lists a [`Key`] by name, followed by
+/// up to four whitespace-separated entries for the base, shift, graph and
+/// shift + graph levels.  An entry is either a single character, `-` for no
+/// character, or a `U+` hexadecimal code point (for whitespace, `-` and
+/// dead keys).  Dead keys are written as the combining diacritical mark they
+/// apply (`U+0300` to `U+036F`), such as `U+0301` for a dead acute.  Levels
+/// left off the end of a line type no character, as do keys that aren't
+/// listed.  Blank lines and lines starting with `#` are ignored.
+///
+/// ```text
+/// # Comment
+/// Digit2 2 @ ² -
+/// Q      q Q @
+/// Space  U+0020 U+0020
+/// Minus  U+002D _
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Layout(Box<[[Option<char>; 4]; 256]>);
+
+impl Default for Layout {
+    fn default() -> Self {
+        Self::qwerty()
+    }
+}
+
+impl Layout {
+    /// Create an empty layout, which doesn't type any characters
+    pub fn empty() -> Self {
+        Self(Box::new([[None; 4]; 256]))
+    }
+
+    /// US QWERTY layout
+    pub fn qwerty() -> Self {
+        Self::from_table(QWERTY)
+    }
+
+    /// US Dvorak layout
+    pub fn dvorak() -> Self {
+        Self::from_table(DVORAK)
+    }
+
+    /// Colemak layout
+    pub fn colemak() -> Self {
+        Self::from_table(COLEMAK)
+    }
+
+    /// French AZERTY layout.
+    ///
+    /// The ISO `< >` key has no [`Key`], so isn't mapped.
+    pub fn azerty() -> Self {
+        Self::from_table(AZERTY)
+    }
+
+    /// German QWERTZ layout.
+    ///
+    /// The ISO `< > |` key has no [`Key`], so isn't mapped.
+    pub fn qwertz() -> Self {
+        Self::from_table(QWERTZ)
+    }
+
+    /// Japanese JIS layout (romaji legends).
+    ///
+    /// The `¥ |` and `\ _` keys have no [`Key`], so aren't mapped.
+    pub fn jis() -> Self {
+        Self::from_table(JIS)
+    }
+
+    /// Build a layout from a table of keys and the characters for each level
+    /// (in [`Level::ALL`] order), where a space means no character
+    fn from_table(table: &[(Key, &str)]) -> Self {
+        let mut layout = Self::empty();
+
+        for (key, levels) in COMMON.iter().chain(table) {
+            for (level, c) in Level::ALL.iter().zip(levels.chars()) {
+                let c = Some(c).filter(|c| *c != ' ' || *key == Key::Space);
+                layout.set(*key, *level, c);
+            }
+        }
+        layout
+    }
+
+    /// Get the character typed by a key at a level
+    pub fn get(&self, key: Key, level: Level) -> Option<char> {
+        self.0[usize::from(key as u8)][level as usize]
+    }
+
+    /// Set the character typed by a key at a level
+    pub fn set(&mut self, key: Key, level: Level, c: Option<char>) {
+        self.0[usize::from(key as u8)][level as usize] = c;
+    }
+
+    /// Get the character typed by pressing a key with a modifier state.
+    ///
+    /// Returns `None` when the app or program modifiers are held, since the
+    /// key press is then a command rather than text.
+    pub fn translate(&self, key: Key, mods: Mods) -> Option<char> {
+        if mods.app() || mods.prg() {
+            return None;
+        }
+        self.get(key, Level::from_mods(mods))
+    }
+}
+
+impl FromStr for Layout {
+    type Err = LayoutError;
+
+    fn from_str(text: &str) -> Result<Self, LayoutError> {
+        let mut layout = Self::empty();
+        let mut listed = [false; 256];
+
+        for (line, text) in text.lines().enumerate() {
+            let error = |column, kind| LayoutError {
+                line: line + 1,
+                column,
+                kind,
+            };
+            if text.trim_start().starts_with('#') {
+                continue;
+            }
+            let mut words = words(text);
+            let (column, name) = match words.next() {
+                Some(word) => word,
+                None => continue,
+            };
+            let key = Key::from_name(name).ok_or_else(|| {
+                error(column, LayoutErrorKind::UnknownKey(name.to_string()))
+            })?;
+            if listed[usize::from(key as u8)] {
+                return Err(error(column, LayoutErrorKind::DuplicateKey(key)));
+            }
+            listed[usize::from(key as u8)] = true;
+            for (i, (column, entry)) in words.enumerate() {
+                let level = *Level::ALL.get(i).ok_or_else(|| {
+                    error(column, LayoutErrorKind::TooManyLevels)
+                })?;
+                let c = parse_entry(entry).ok_or_else(|| {
+                    let entry = entry.to_string();
+                    error(column, LayoutErrorKind::InvalidEntry(entry))
+                })?;
+                layout.set(key, level, c);
+            }
+        }
+
+        Ok(layout)
+    }
+}
+
+impl fmt::Display for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for key in (0..=u8::MAX).map(Key::from) {
+            let levels = &self.0[usize::from(key as u8)];
+            let count = match levels.iter().rposition(Option::is_some) {
+                Some(last) => last + 1,
+                None => continue,
+            };
+            write!(f, "{:?}", key)?;
+            for c in &levels[..count] {
+                match c {
+                    None => write!(f, " -")?,
+                    Some(c)
+                        if c.is_whitespace() || c.is_control() || is_dead(*c) =>
+                    {
+                        write!(f, " U+{:04X}", u32::from(*c))?
+                    }
+                    Some('-') => write!(f, " U+002D")?,
+                    Some(c) => write!(f, " {}", c)?,
+                }
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Split a line into words, along with their starting columns
+fn words(line: &str) -> impl Iterator<Item = (usize, &str)> {
+    let mut column = 0;
+    let mut chars = line.char_indices().peekable();
+
+    std::iter::from_fn(move || {
+        while let Some((_, c)) = chars.peek() {
+            if !c.is_whitespace() {
+                break;
+            }
+            chars.next();
+            column += 1;
+        }
+        let (start, _) = *chars.peek()?;
+        let first = column + 1;
+        let mut end = line.len();
+        while let Some((i, c)) = chars.peek() {
+            if c.is_whitespace() {
+                end = *i;
+                break;
+            }
+            chars.next();
+            column += 1;
+        }
+        Some((first, &line[start..end]))
+    })
+}
+
+/// Parse a layout entry: a character, `-` for none, or a `U+` code point
+fn parse_entry(entry: &str) -> Option<Option<char>> {
+    if entry == "-" {
+        return Some(None);
+    }
+    if let Some(hex) = entry.strip_prefix("U+") {
+        let code = u32::from_str_radix(hex, 16).ok()?;
+        return char::from_u32(code).map(Some);
+    }
+    let mut chars = entry.chars();
+    let c = chars.next()?;
+    chars.next().is_none().then(|| Some(c))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Keys typing characters on the main block, in rows
+    const ROWS: [Key; 47] = [
+        Key::Grave,
+        Key::Digit1,
+        Key::Digit2,
+        Key::Digit3,
+        Key::Digit4,
+        Key::Digit5,
+        Key::Digit6,
+        Key::Digit7,
+        Key::Digit8,
+        Key::Digit9,
+        Key::Digit0,
+        Key::Minus,
+        Key::Equals,
+        Key::Q,
+        Key::W,
+        Key::E,
+        Key::R,
+        Key::T,
+        Key::Y,
+        Key::U,
+        Key::I,
+        Key::O,
+        Key::P,
+        Key::LeftBracket,
+        Key::RightBracket,
+        Key::Backslash,
+        Key::A,
+        Key::S,
+        Key::D,
+        Key::F,
+        Key::G,
+        Key::H,
+        Key::J,
+        Key::K,
+        Key::L,
+        Key::Semicolon,
+        Key::Apostrophe,
+        Key::Z,
+        Key::X,
+        Key::C,
+        Key::V,
+        Key::B,
+        Key::N,
+        Key::M,
+        Key::Comma,
+        Key::Period,
+        Key::Slash,
+    ];
+
+    /// Check every key at every level of a layout against the base and
+    /// shift levels of [`ROWS`] (a space meaning no character), and the
+    /// keys typing characters at the graph level
+    fn check(layout: Layout, base: &str, shift: &str, graph: &[(Key, char)]) {
+        let mut expected = Layout::empty();
+
+        assert_eq!(base.chars().count(), ROWS.len());
+        assert_eq!(shift.chars().count(), ROWS.len());
+        for (key, (base, shift)) in
+            ROWS.iter().zip(base.chars().zip(shift.chars()))
+        {
+            expected.set(*key, Level::Base, Some(base).filter(|c| *c != ' '));
+            expected.set(*key, Level::Shift, Some(shift).filter(|c| *c != ' '));
+        }
+        for (key, c) in graph {
+            expected.set(*key, Level::Graph, Some(*c));
+        }
+        expected.set(Key::Space, Level::Base, Some(' '));
+        expected.set(Key::Space, Level::Shift, Some(' '));
+        for (key, c) in [
+            (Key::NumDivide, '/'),
+            (Key::NumMultiply, '*'),
+            (Key::NumSubtract, '-'),
+            (Key::NumAdd, '+'),
+            (Key::NumComma, ','),
+            (Key::NumDecimalPoint, '.'),
+            (Key::Num0, '0'),
+            (Key::Num1, '1'),
+            (Key::Num2, '2'),
+            (Key::Num3, '3'),
+            (Key::Num4, '4'),
+            (Key::Num5, '5'),
+            (Key::Num6, '6'),
+            (Key::Num7, '7'),
+            (Key::Num8, '8'),
+            (Key::Num9, '9'),
+        ] {
+            expected.set(key, Level::Base, Some(c));
+            expected.set(key, Level::Shift, Some(c));
+        }
+
+        for key in (0..=u8::MAX).map(Key::from) {
+            for level in Level::ALL {
+                assert_eq!(
+                    layout.get(key, level),
+                    expected.get(key, level),
+                    "{:?} at {:?}",
+                    key,
+                    level,
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn qwerty() {
+        check(
+            Layout::qwerty(),
+            "`1234567890-=qwertyuiop[]\\asdfghjkl;'zxcvbnm,./",
+            "~!@#$%^&*()_+QWERTYUIOP{}|ASDFGHJKL:\"ZXCVBNM<>?",
+            &[],
+        );
+    }
+
+    #[test]
+    fn azerty() {
+        check(
+            Layout::azerty(),
+            "²&é\"'(-è_çà)=azertyuiop\u{302}$*qsdfghjklmùwxcvbn,;:!",
+            " 1234567890°+AZERTYUIOP\u{308}£µQSDFGHJKLM%WXCVBN?./§",
+            &[
+                (Key::Digit2, '~'),
+                (Key::Digit3, '#'),
+                (Key::Digit4, '{'),
+                (Key::Digit5, '['),
+                (Key::Digit6, '|'),
+                (Key::Digit7, '`'),
+                (Key::Digit8, '\\'),
+                (Key::Digit9, '^'),
+                (Key::Digit0, '@'),
+                (Key::Minus, ']'),
+                (Key::Equals, '}'),
+                (Key::E, '€'),
+                (Key::RightBracket, '¤'),
+            ],
+        );
+    }
+
+    #[test]
+    fn qwertz() {
+        check(
+            Layout::qwertz(),
+            "\u{302}1234567890ß\u{301}qwertzuiopü+#asdfghjklöäyxcvbnm,.-",
+            "°!\"§$%&/()=?\u{300}QWERTZUIOPÜ*'ASDFGHJKLÖÄYXCVBNM;:_",
+            &[
+                (Key::Digit2, '²'),
+                (Key::Digit3, '³'),
+                (Key::Digit7, '{'),
+                (Key::Digit8, '['),
+                (Key::Digit9, ']'),
+                (Key::Digit0, '}'),
+                (Key::Minus, '\\'),
+                (Key::Q, '@'),
+                (Key::E, '€'),
+                (Key::RightBracket, '~'),
+                (Key::M, 'µ'),
+            ],
+        );
+    }
+
+    #[test]
+    fn jis() {
+        check(
+            Layout::jis(),
+            " 1234567890-^qwertyuiop@[]asdfghjkl;:zxcvbnm,./",
+            " !\"#$%&'() =~QWERTYUIOP`{}ASDFGHJKL+*ZXCVBNM<>?",
+            &[],
+        );
+    }
+
+    #[test]
+    fn text_format() {
+        for layout in [
+            Layout::empty(),
+            Layout::qwerty(),
+            Layout::dvorak(),
+            Layout::colemak(),
+            Layout::azerty(),
+            Layout::qwertz(),
+            Layout::jis(),
+        ] {
+            assert_eq!(layout.to_string().parse::<Layout>(), Ok(layout));
+        }
+
+        let layout: Layout = "# Comment\n\
+            Digit2 2 @ ² -\n\
+            \n\
+            Q      q Q @\n\
+            Space  U+0020 U+0020\n\
+            Minus  U+002D _\n"
+            .parse()
+            .unwrap();
+        assert_eq!(layout.get(Key::Digit2, Level::Graph), Some('²'));
+        assert_eq!(layout.get(Key::Digit2, Level::ShiftGraph), None);
+        assert_eq!(layout.get(Key::Q, Level::Graph), Some('@'));
+        assert_eq!(layout.get(Key::Space, Level::Base), Some(' '));
+        assert_eq!(layout.get(Key::Minus, Level::Base), Some('-'));
+        assert_eq!(layout.get(Key::W, Level::Base), None);
+        assert_eq!(
+            layout.to_string(),
+            "Digit2 2 @ ²\nMinus U+002D _\nQ q Q @\nSpace U+0020 U+0020\n",
+        );
+    }
+
+    #[test]
+    fn text_format_errors() {
+        let error =
+            |line, column, kind| Err(LayoutError { line, column, kind });
+
+        assert_eq!(
+            "Q q\n  Nope a".parse::<Layout>(),
+            error(2, 3, LayoutErrorKind::UnknownKey("Nope".to_string())),
+        );
+        assert_eq!(
+            "Q q\nQ a".parse::<Layout>(),
+            error(2, 1, LayoutErrorKind::DuplicateKey(Key::Q)),
+        );
+        assert_eq!(
+            "Q qq".parse::<Layout>(),
+            error(1, 3, LayoutErrorKind::InvalidEntry("qq".to_string())),
+        );
+        assert_eq!(
+            "Q U+D800".parse::<Layout>(),
+            error(1, 3, LayoutErrorKind::InvalidEntry("U+D800".to_string())),
+        );
+        assert_eq!(
+            "Q a b c d e".parse::<Layout>(),
+            error(1, 11, LayoutErrorKind::TooManyLevels),
+        );
+    }
+}
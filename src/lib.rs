@@ -1,8 +1,54 @@
 //! Rust library for sending and receiving asynchronous keyboard events.
 
+mod compose;
+mod dead;
+mod decode;
+#[cfg(feature = "evdev")]
+mod evdev;
+mod event;
+mod hangul;
+mod kana;
 mod key;
+mod keymap;
+mod keyset;
+mod layout;
+mod macros;
+mod record;
+#[cfg(feature = "remote")]
+pub mod remote;
+mod repeat;
+mod sequence;
+mod shortcut;
+mod wire;
+#[cfg(feature = "term")]
+pub mod term;
 
+pub use self::compose::{ComposeError, ComposeErrorKind, ComposeTable};
+pub use self::decode::{DecodeError, Decoded};
+#[cfg(feature = "evdev")]
+pub use self::evdev::Evdev;
+pub use self::event::{Event, Events};
 pub use self::key::Key;
+pub use self::keymap::{Keymap, KeymapEvent, Mode, ModeChange};
+pub use self::keyset::KeySet;
+pub use self::layout::{Layout, LayoutError, LayoutErrorKind, Level};
+pub use self::macros::{Macro, MacroError, MacroErrorKind, MacroRecorder};
+pub use self::record::{
+    Playback, Recording, RecordingError, RecordingErrorKind,
+};
+pub use self::repeat::Repeat;
+pub use self::sequence::{Sequence, SequenceEvent, SequenceMap};
+pub use self::shortcut::{Shortcut, ShortcutError, ShortcutMap};
+pub use self::wire::{Decoder, Encoder};
+use self::{
+    hangul::Hangul,
+    kana::Kana,
+    repeat::{Held, Timer},
+};
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    time::Instant,
+};
 use whisk::Channel;
 use pasts::prelude::*;
 
@@ -118,22 +164,217 @@ impl Mods {
     }
 }
 
+/// Keyboard lock state
+#[derive(Copy, Clone, Default, Debug, Eq, PartialEq)]
+pub struct Locks(u8);
+
+impl Locks {
+    const SHIFT: u8 = 0b0010_0000;
+    const NUM: u8 = 0b0001_0000;
+    const SCROLL: u8 = 0b0000_1000;
+    const SYS: u8 = 0b0000_0100;
+    const APP: u8 = 0b0000_0010;
+    const PRG: u8 = 0b0000_0001;
+    /// All of the lock bits
+    const ALL: u8 = 0b0011_1111;
+
+    fn set(&mut self, what: u8, to: bool) {
+        if to {
+            self.0 |= what;
+        } else {
+            self.0 &= !what;
+        }
+    }
+
+    fn get(&self, what: u8) -> bool {
+        self.0 & what != 0
+    }
+
+    /// Create a new lock state (with all locks off)
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set ShiftLk (toggled by pressing both shift keys)
+    pub fn set_shift(&mut self, value: bool) {
+        self.set(Self::SHIFT, value)
+    }
+
+    /// Set NumLock
+    pub fn set_num(&mut self, value: bool) {
+        self.set(Self::NUM, value)
+    }
+
+    /// Set ScrollLock
+    pub fn set_scroll(&mut self, value: bool) {
+        self.set(Self::SCROLL, value)
+    }
+
+    /// Set SysLock
+    pub fn set_sys(&mut self, value: bool) {
+        self.set(Self::SYS, value)
+    }
+
+    /// Set application lock (LeftAppLock / RightAppLock)
+    pub fn set_app(&mut self, value: bool) {
+        self.set(Self::APP, value)
+    }
+
+    /// Set program lock (LeftPrgLock / RightPrgLock)
+    pub fn set_prg(&mut self, value: bool) {
+        self.set(Self::PRG, value)
+    }
+
+    /// Get ShiftLk (toggled by pressing both shift keys)
+    pub fn shift(&self) -> bool {
+        self.get(Self::SHIFT)
+    }
+
+    /// Get NumLock
+    pub fn num(&self) -> bool {
+        self.get(Self::NUM)
+    }
+
+    /// Get ScrollLock
+    pub fn scroll(&self) -> bool {
+        self.get(Self::SCROLL)
+    }
+
+    /// Get SysLock
+    pub fn sys(&self) -> bool {
+        self.get(Self::SYS)
+    }
+
+    /// Get application lock (LeftAppLock / RightAppLock)
+    pub fn app(&self) -> bool {
+        self.get(Self::APP)
+    }
+
+    /// Get program lock (LeftPrgLock / RightPrgLock)
+    pub fn prg(&self) -> bool {
+        self.get(Self::PRG)
+    }
+}
+
+/// Message sent by a typer
+#[derive(Debug)]
+enum Message {
+    Type(TypeInternal),
+    Reset,
+    Resync(KeySet),
+}
+
+/// Message sent by a typer, with the time and device if the typer set them
+#[derive(Debug)]
+struct Sent(Message, Option<Instant>, Option<u32>);
+
 /// Source of keyboard events
 #[derive(Clone, Debug)]
-pub struct Typer(Channel<TypeInternal>);
+pub struct Typer(Channel<Sent>, Option<u32>);
 
 impl Typer {
-    /// Send a keyboard type event
+    /// Send a keyboard type event (timestamped when it's received)
     pub fn send(&self, typed: Type) -> impl Future<Output = ()> + Send + Unpin {
-        self.0.send(typed.into())
+        self.0.send(Sent(Message::Type(typed.into()), None, self.1))
+    }
+
+    /// Send a keyboard event with its timestamp and device (or the typer's
+    /// device, if not set)
+    pub fn send_event(
+        &self,
+        event: Event,
+    ) -> impl Future<Output = ()> + Send + Unpin {
+        let device = event.device.or(self.1);
+
+        let typed = Message::Type(event.typed.into());
+
+        self.0.send(Sent(typed, Some(event.time), device))
+    }
+
+    /// Tell the keyboard focus was lost (see [`Keyboard::reset()`])
+    pub fn reset(&self) -> impl Future<Output = ()> + Send + Unpin {
+        self.0.send(Sent(Message::Reset, None, self.1))
+    }
+
+    /// Tell the keyboard focus was gained, with the keys currently held down
+    /// (see [`Keyboard::resync()`])
+    pub fn resync(
+        &self,
+        pressed: KeySet,
+    ) -> impl Future<Output = ()> + Send + Unpin {
+        self.0.send(Sent(Message::Resync(pressed), None, self.1))
+    }
+
+    /// Get the device the typer sends events from, if any
+    pub fn device(&self) -> Option<u32> {
+        self.1
     }
 }
 
-/// Keyboard handle
+/// Key state of a device
 #[derive(Debug, Default)]
+struct DeviceKeys {
+    pressed: KeySet,
+    /// Numpad keys pressed while NumLock was off
+    navigating: KeySet,
+    /// Whether a modifier key has been pressed
+    modifiers: bool,
+}
+
+/// Keyboard handle
+#[derive(Debug)]
 pub struct Keyboard {
-    state: [u64; 4],
-    channel: Channel<TypeInternal>,
+    devices: BTreeMap<Option<u32>, DeviceKeys>,
+    ignored: BTreeSet<Option<u32>>,
+    channel: Channel<Sent>,
+    device_channels: BTreeMap<u32, Channel<Sent>>,
+    /// Index of the channel to poll first (the main channel, then the
+    /// device channels in order)
+    next_channel: usize,
+    layout: Option<Layout>,
+    dead: Option<char>,
+    compose: Option<ComposeTable>,
+    composing: Option<String>,
+    hangul: Option<Hangul>,
+    kana: Option<Kana>,
+    trust_mods: bool,
+    strict: bool,
+    locks: Locks,
+    repeat: Option<Repeat>,
+    held: Option<Held>,
+    timer: Option<Timer>,
+    /// Time and device of the event being processed
+    stamp: Option<(Instant, Option<u32>)>,
+    pending: VecDeque<Result<Event, DecodeError>>,
+}
+
+impl Default for Keyboard {
+    fn default() -> Self {
+        let mut locks = Locks::new();
+        locks.set_num(true);
+
+        Self {
+            devices: BTreeMap::new(),
+            ignored: BTreeSet::new(),
+            channel: Channel::default(),
+            device_channels: BTreeMap::new(),
+            next_channel: 0,
+            layout: None,
+            dead: None,
+            compose: None,
+            composing: None,
+            hangul: None,
+            kana: None,
+            trust_mods: false,
+            strict: false,
+            locks,
+            repeat: None,
+            held: None,
+            timer: None,
+            stamp: None,
+            pending: VecDeque::new(),
+        }
+    }
 }
 
 impl Keyboard {
@@ -144,76 +385,881 @@ impl Keyboard {
 
     /// Create a new typer
     pub fn typer(&self) -> Typer {
-        Typer(self.channel.clone())
+        Typer(self.channel.clone(), None)
     }
-}
 
-impl Notifier for Keyboard {
-    type Event = Type;
+    /// Create a new typer for a device, which sets the device of the events
+    /// it sends.  Each device has its own key and modifier state, so keys
+    /// held down on one device aren't released by another.
+    pub fn device_typer(&mut self, device: u32) -> Typer {
+        let channel = self.device_channels.entry(device).or_default();
 
-    fn poll_next(mut self: Pin<&mut Self>, exec: &mut Exec<'_>) -> Poll<Type> {
-        if let Ready(typed) = Pin::new(&mut self.channel).poll_next(exec) {
-            let typed = typed.try_into().unwrap();
-            // De-duplication
-            match typed {
-                Type::Press(key, _) => {
-                    let key = key as u8;
-                    let lvl = key / 64;
-                    let key = key % 64;
-                    if self.state[usize::from(lvl)] & (1 << key) != 0 {
-                        return Pending;
-                    } else {
-                        self.state[usize::from(lvl)] |= 1 << key;
+        Typer(channel.clone(), Some(device))
+    }
+
+    /// Set whether to ignore the events sent from a device (or `None` for
+    /// events sent without one)
+    pub fn set_ignored(&mut self, device: Option<u32>, ignore: bool) {
+        if ignore {
+            self.ignored.insert(device);
+        } else {
+            self.ignored.remove(&device);
+        }
+    }
+
+    /// Check if the events sent from a device are ignored
+    pub fn is_ignored(&self, device: Option<u32>) -> bool {
+        self.ignored.contains(&device)
+    }
+
+    /// Release all keys held down, for when focus is lost and key releases
+    /// won't be received.
+    ///
+    /// A [`Type::Release`] is sent for each key held down, ordered by device
+    /// (`None` first) and then by key code, which clears the modifiers
+    /// derived from them.  Locks are left on.
+    pub fn reset(&mut self) {
+        self.release_all(KeySet::new());
+    }
+
+    /// Resynchronize with the keys currently held down, for when focus is
+    /// gained.
+    ///
+    /// Keys that aren't held down anymore are released as with
+    /// [`Keyboard::reset()`].  A [`Type::Press`] is then sent for each newly
+    /// held key (in key code order) on the typer's device, without typing
+    /// text, repeating or toggling locks, so held modifiers apply again.
+    pub fn resync(&mut self, pressed: KeySet) {
+        self.release_all(pressed);
+
+        let held = self.pressed();
+        for key in pressed.iter().filter(|key| !held.contains(*key)) {
+            self.keys().pressed.insert(key);
+            if !self.locks.num() && navigation(key).is_some() {
+                self.keys().navigating.insert(key);
+            }
+            let mods = self.event_mods(Mods::new());
+            let sent = self.navigation(key);
+            self.push(Type::Press(sent, mods));
+        }
+    }
+
+    /// Get the devices that have sent key events (including `None` for
+    /// events sent without one)
+    pub fn devices(&self) -> impl Iterator<Item = Option<u32>> + '_ {
+        self.devices.keys().copied()
+    }
+
+    /// Get a notifier yielding events with their timestamps and devices,
+    /// instead of just the [`Type`]
+    pub fn events(&mut self) -> Events<'_> {
+        Events(self)
+    }
+
+    /// Get a notifier yielding events, or the errors decoding the events
+    /// sent to the keyboard (which are otherwise dropped)
+    pub fn decoded(&mut self) -> Decoded<'_> {
+        Decoded(self)
+    }
+
+    /// Set whether events sent to the keyboard are decoded strictly
+    /// (`false` by default).
+    ///
+    /// When not strict, events that can be repaired are, instead of failing
+    /// to decode (see [`DecodeError`]).  Events sent through a [`Typer`] are
+    /// always valid, except for a [`Type::Preedit`] cursor that isn't on a
+    /// character boundary, so that's the only repair this affects; events
+    /// read with a [`Decoder`] or from a [`Recording`] have their own
+    /// setting.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    /// Check if events sent to the keyboard are decoded strictly
+    pub fn strict(&self) -> bool {
+        self.strict
+    }
+
+    /// Check if a key is currently held down (on any device)
+    pub fn is_pressed(&self, key: Key) -> bool {
+        self.devices.values().any(|keys| keys.pressed.contains(key))
+    }
+
+    /// Get the set of keys currently held down (on any device)
+    pub fn pressed(&self) -> KeySet {
+        self.devices
+            .values()
+            .fold(KeySet::new(), |set, keys| set.union(&keys.pressed))
+    }
+
+    /// Get the set of keys currently held down on a device
+    pub fn pressed_on(&self, device: Option<u32>) -> KeySet {
+        self.devices
+            .get(&device)
+            .map_or_else(KeySet::new, |keys| keys.pressed)
+    }
+
+    /// Get the modifier state derived from the keys currently held down (on
+    /// any device) and the locks that are on
+    pub fn mods(&self) -> Mods {
+        self.mods_of(self.pressed())
+    }
+
+    /// Get the modifier state derived from the keys currently held down on a
+    /// device and the locks that are on
+    pub fn mods_on(&self, device: Option<u32>) -> Mods {
+        self.mods_of(self.pressed_on(device))
+    }
+
+    /// Get the modifier state derived from a set of keys held down
+    fn mods_of(&self, pressed: KeySet) -> Mods {
+        let held = |keys: &[Key]| keys.iter().any(|k| pressed.contains(*k));
+        let locks = self.locks;
+        let mut mods = Mods::new();
+        let shift = held(&[Key::LeftShift, Key::RightShift]);
+
+        mods.set_shift(locks.shift() || shift);
+        mods.set_app(locks.app() || held(&[Key::LeftApp, Key::RightApp]));
+        mods.set_prg(locks.prg() || held(&[Key::LeftPrg, Key::RightPrg]));
+        mods.set_graph(held(&[Key::Graph]));
+        mods.set_emoji(held(&[Key::Space]));
+        mods.set_composing(self.composing.is_some());
+        mods
+    }
+
+    /// Get the lock state
+    pub fn locks(&self) -> Locks {
+        self.locks
+    }
+
+    /// Set the lock state (for example, to match the keyboard's LEDs),
+    /// sending a [`Type::Lock`] if it changed.
+    ///
+    /// NumLock is on and the other locks are off by default, and they're
+    /// toggled by pressing their lock keys.  ShiftLk is toggled by pressing a
+    /// shift key while the other one is held down.  While NumLock is off,
+    /// numpad keys that have one are sent as their navigation key (for
+    /// example, [`Key::Num8`] is sent as [`Key::NumUp`]) instead of typing
+    /// characters.
+    pub fn set_locks(&mut self, locks: Locks) {
+        if locks != self.locks {
+            self.locks = locks;
+            self.push(Type::Lock(locks));
+        }
+    }
+
+    /// Set whether to trust the modifiers sent with key events by [`Typer`]s
+    /// (`false` by default).
+    ///
+    /// When not trusted, the shift, app, program, graph and emoji modifiers
+    /// are replaced with the ones derived from the keys held down on the
+    /// event's device and the locks (see [`Keyboard::mods_on()`]), so typers
+    /// can send keys without modifiers.  Devices that haven't sent any
+    /// modifier key presses (such as terminals, which only report modifiers
+    /// with other keys) still have the modifiers they send added.
+    pub fn set_trust_mods(&mut self, trust: bool) {
+        self.trust_mods = trust;
+    }
+
+    /// Check if the modifiers sent with key events by [`Typer`]s are trusted
+    pub fn trust_mods(&self) -> bool {
+        self.trust_mods
+    }
+
+    /// Get the modifiers for a key event, given the ones it was sent with
+    fn event_mods(&self, mut sent: Mods) -> Mods {
+        if self.trust_mods {
+            sent.set_composing(self.composing.is_some());
+            return sent;
+        }
+        let device = self.device();
+        let mut mods = self.mods_on(device);
+        if !self.devices.get(&device).map_or(false, |keys| keys.modifiers) {
+            mods.0 |= sent.0 & !Mods::COMPOSING;
+        }
+        mods.set_ltb(sent.ltb());
+        mods.set_rtb(sent.rtb());
+        mods
+    }
+
+    /// Set the layout used to generate [`Type::Char`] events after each
+    /// [`Type::Press`], or `None` to only pass along characters sent by
+    /// [`Typer`]s (the default).
+    ///
+    /// Dead keys in the layout don't generate a character until the next
+    /// character key is pressed, which then types the composed character, or
+    /// both characters when they don't compose.  The dead key is typed as
+    /// its spacing form (such as `´` for a dead acute), or as a space
+    /// followed by the combining mark when it has none.  Pressing a key that
+    /// doesn't type a character (other than a modifier) types the dead key
+    /// on its own, before the key press.
+    pub fn set_layout(&mut self, layout: Option<Layout>) {
+        self.layout = layout;
+        self.dead = None;
+    }
+
+    /// Get the layout used to generate [`Type::Char`] events, if any
+    pub fn layout(&self) -> Option<&Layout> {
+        self.layout.as_ref()
+    }
+
+    /// Set the table of compose sequences, or `None` to disable composition
+    /// (the default).
+    ///
+    /// Pressing [`Key::Compose`] starts composing, and the characters typed
+    /// after it are buffered until they complete a sequence in the table,
+    /// which types its result instead.  Composing is cancelled by typing a
+    /// character that doesn't continue any sequence, or pressing
+    /// [`Key::Escape`] (sending [`Type::Cancel`] after the press).  While
+    /// composing, [`Mods::composing()`] is set on key events.
+    pub fn set_compose(&mut self, table: Option<ComposeTable>) {
+        self.compose = table;
+        self.composing = None;
+    }
+
+    /// Get the table of compose sequences, if any
+    pub fn compose(&self) -> Option<&ComposeTable> {
+        self.compose.as_ref()
+    }
+
+    /// Set the typematic key repeat timing, or `None` to turn off key repeat
+    /// (the default).
+    ///
+    /// While a key is held down, [`Type::Repeat`] events (and the characters
+    /// they type) are generated for it, until it's released or another key is
+    /// pressed.  Modifier keys don't repeat.  Repeats sent by typers (such as
+    /// the kernel's key repeat read from evdev) are dropped while this is on,
+    /// so keys don't repeat twice.
+    pub fn set_repeat(&mut self, repeat: Option<Repeat>) {
+        self.repeat = repeat;
+        self.held = None;
+    }
+
+    /// Get the typematic key repeat timing, if key repeat is turned on
+    pub fn repeat(&self) -> Option<Repeat> {
+        self.repeat
+    }
+
+    /// Check if the Hangul input method is active
+    pub fn hangul(&self) -> bool {
+        self.hangul.is_some()
+    }
+
+    /// Turn the 2-set (Dubeolsik) Hangul input method on or off (also toggled
+    /// by pressing [`Key::Hangul`]).
+    ///
+    /// While on, keys are typed as Korean jamo by their position (regardless
+    /// of the layout), which are combined into syllables.  The syllable being
+    /// composed is available from [`Keyboard::preedit()`], and is typed once
+    /// it's complete.  Turning the input method off types any syllable being
+    /// composed.  Turning it on turns off the kana input method.
+    ///
+    /// Converting to hanja needs a dictionary, which isn't included, so
+    /// [`Key::Hanja`] types the syllable being composed and is sent as a key
+    /// press for the application to handle.
+    pub fn set_hangul(&mut self, on: bool) {
+        if on {
+            self.set_kana(false);
+        }
+        match self.hangul.take() {
+            Some(mut hangul) if !on => {
+                let mut commit = String::new();
+                hangul.flush(&mut commit);
+                self.edited(commit, String::new());
+            }
+            hangul => {
+                self.hangul = hangul.or_else(|| on.then(Hangul::new));
+            }
+        }
+    }
+
+    /// Check if the kana input method is active
+    pub fn kana(&self) -> bool {
+        self.kana.is_some()
+    }
+
+    /// Turn the Japanese kana input method on or off (also toggled by
+    /// pressing [`Key::Kana`]).
+    ///
+    /// While on, characters typed with the layout are read as wāpuro romaji
+    /// and converted to full-width hiragana (ん is typed as "n" before a
+    /// consonant, "n'" or "nn").  Pressing [`Key::Convert`]
+    /// switches between hiragana and katakana, [`Key::Kanji`] switches
+    /// between full-width and half-width (katakana, punctuation and ASCII),
+    /// and [`Key::NonConvert`] types the romaji not converted yet as-is.
+    /// Romaji not converted yet is available from [`Keyboard::preedit()`].
+    /// Turning the input method off converts any remaining romaji.  Turning
+    /// it on turns off the Hangul input method.
+    pub fn set_kana(&mut self, on: bool) {
+        if on {
+            self.set_hangul(false);
+        }
+        match self.kana.take() {
+            Some(mut kana) if !on => {
+                let mut commit = String::new();
+                kana.flush(&mut commit);
+                self.edited(commit, String::new());
+            }
+            kana => self.kana = kana.or_else(|| on.then(Kana::new)),
+        }
+    }
+
+    /// Get the text being composed by an input method or compose sequence,
+    /// which hasn't been typed yet (last sent as [`Type::Preedit`])
+    pub fn preedit(&self) -> String {
+        let hangul = self.hangul.as_ref().and_then(Hangul::preedit);
+        let kana = self.kana.as_ref().map_or("", Kana::preedit);
+        let compose = self.composing.as_deref().unwrap_or("");
+
+        hangul
+            .into_iter()
+            .chain(kana.chars())
+            .chain(compose.chars())
+            .collect()
+    }
+
+    /// Process an event received from a typer, queueing the resulting events
+    fn process(&mut self, typed: Type) {
+        // De-duplication
+        match typed {
+            Type::Press(key, mods) => {
+                if !self.keys().pressed.insert(key) {
+                    return;
+                }
+                if sequence::modifier(key) {
+                    self.keys().modifiers = true;
+                }
+                let pressed = self.keys().pressed;
+                let mut locks = self.locks;
+                let mut cancel = false;
+                match key {
+                    Key::Compose if self.compose.is_some() => {
+                        self.composing.get_or_insert_with(String::new);
+                    }
+                    Key::Escape if self.composing.take().is_some() => {
+                        cancel = true;
+                    }
+                    Key::Hangul => self.set_hangul(!self.hangul()),
+                    Key::Kana => self.set_kana(!self.kana()),
+                    Key::LeftShift if pressed.contains(Key::RightShift) => {
+                        locks.set_shift(!locks.shift())
+                    }
+                    Key::RightShift if pressed.contains(Key::LeftShift) => {
+                        locks.set_shift(!locks.shift())
+                    }
+                    Key::NumLock => locks.set_num(!locks.num()),
+                    Key::ScrollLock => locks.set_scroll(!locks.scroll()),
+                    Key::SysLock => locks.set_sys(!locks.sys()),
+                    Key::LeftAppLock | Key::RightAppLock => {
+                        locks.set_app(!locks.app())
+                    }
+                    Key::LeftPrgLock | Key::RightPrgLock => {
+                        locks.set_prg(!locks.prg())
                     }
+                    _ => {}
                 }
-                Type::Release(key, _) => {
-                    let key = key as u8;
-                    let lvl = key / 64;
-                    let key = key % 64;
-                    if self.state[usize::from(lvl)] & (1 << key) == 0 {
-                        return Pending;
-                    } else {
-                        self.state[usize::from(lvl)] &= !(1 << key);
+                if !self.locks.num() && navigation(key).is_some() {
+                    self.keys().navigating.insert(key);
+                }
+                let toggled = locks != self.locks;
+                self.locks = locks;
+                let mods = self.event_mods(mods);
+                let sent = self.navigation(key);
+                let layout = self.layout.as_ref();
+                // A pending dead key is resolved by the next key, even one
+                // that doesn't type a character
+                if !sequence::modifier(key)
+                    && layout.and_then(|l| l.translate(sent, mods)).is_none()
+                {
+                    if let Some(dead) = self.dead.take() {
+                        dead::spacing(dead).for_each(|c| self.emit_char(c));
                     }
                 }
-                Type::Char(_) => {},
+                self.push(Type::Press(sent, mods));
+                if cancel {
+                    self.push(Type::Cancel);
+                }
+                if toggled {
+                    self.push(Type::Lock(locks));
+                }
+                let repeat = self.repeat.filter(|_| repeats(key));
+                let device = self.device();
+                self.held = repeat.map(|r| Held {
+                    key,
+                    mods,
+                    device,
+                    next: Instant::now() + r.delay(),
+                });
+                self.type_key(sent, mods);
             }
-            Ready(typed)
+            Type::Repeat(key, mods) => {
+                if !self.keys().pressed.contains(key) {
+                    return;
+                }
+                let mods = self.event_mods(mods);
+                let sent = self.navigation(key);
+                self.push(Type::Repeat(sent, mods));
+                self.type_key(sent, mods);
+            }
+            Type::Release(key, mods) => {
+                if !self.keys().pressed.remove(key) {
+                    return;
+                }
+                let device = self.device();
+                if self.held.as_ref().map_or(false, |held| {
+                    held.key == key && held.device == device
+                }) {
+                    self.held = None;
+                }
+                let mods = self.event_mods(mods);
+                let sent = self.navigation(key);
+                self.keys().navigating.remove(key);
+                self.push(Type::Release(sent, mods));
+            }
+            Type::Lock(locks) => self.set_locks(locks),
+            Type::Char(c) => self.emit_char(c),
+            typed => self.push(typed),
+        }
+    }
+
+    /// Release the keys held down on every device, except those to keep
+    fn release_all(&mut self, keep: KeySet) {
+        let stamp = self.stamp;
+        let time = stamp.map_or_else(Instant::now, |(time, _)| time);
+        let devices = self.devices.keys().copied().collect::<Vec<_>>();
+
+        for device in devices {
+            self.stamp = Some((time, device));
+            for key in self.keys().pressed.iter().collect::<Vec<_>>() {
+                if !keep.contains(key) {
+                    self.process(Type::Release(key, Mods::new()));
+                }
+            }
+        }
+        self.stamp = stamp;
+    }
+
+    /// Get the device of the event being processed
+    fn device(&self) -> Option<u32> {
+        self.stamp.and_then(|(_, device)| device)
+    }
+
+    /// Get the key state of the device of the event being processed
+    fn keys(&mut self) -> &mut DeviceKeys {
+        self.devices.entry(self.device()).or_default()
+    }
+
+    /// Get the key to send for a key held down, which is its navigation key
+    /// if it's a numpad key pressed while NumLock was off
+    fn navigation(&mut self, key: Key) -> Key {
+        if self.keys().navigating.contains(key) {
+            navigation(key).unwrap_or(key)
+        } else {
+            key
+        }
+    }
+
+    /// Type the text for a key press or repeat
+    fn type_key(&mut self, key: Key, mods: Mods) {
+        let layout = self.layout.as_ref();
+        let c = layout.and_then(|l| l.translate(key, mods));
+
+        if self.input_method(key, mods, c) {
+            return;
+        }
+        if let Some(c) = c {
+            self.type_char(c);
+        }
+    }
+
+    /// Queue an event, with the time and device of the event being processed
+    /// (or now, if not processing one)
+    fn push(&mut self, typed: Type) {
+        let now = || (Instant::now(), None);
+        let (time, device) = self.stamp.unwrap_or_else(now);
+
+        self.pending.push_back(Ok(Event {
+            typed,
+            time,
+            device,
+        }));
+    }
+
+    /// Poll for the next event, skipping decoding errors
+    pub(crate) fn poll_event(&mut self, exec: &mut Exec<'_>) -> Poll<Event> {
+        loop {
+            match self.poll_decoded(exec) {
+                Ready(Ok(event)) => return Ready(event),
+                Ready(Err(_)) => {}
+                Pending => return Pending,
+            }
+        }
+    }
+
+    /// Poll for the next event, or error decoding one
+    pub(crate) fn poll_decoded(
+        &mut self,
+        exec: &mut Exec<'_>,
+    ) -> Poll<Result<Event, DecodeError>> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Ready(event);
+            }
+            if let Ready(sent) = self.poll_channels(exec) {
+                self.receive(sent);
+            } else if !self.repeat_held(exec) {
+                return Pending;
+            }
+        }
+    }
+
+    /// Process a message sent by a typer, queueing the resulting events
+    fn receive(&mut self, sent: Sent) {
+        let Sent(message, time, device) = sent;
+
+        if self.ignored.contains(&device) {
+            return;
+        }
+        self.stamp = Some((time.unwrap_or_else(Instant::now), device));
+        match message {
+            Message::Type(typed) => match typed.decode(self.strict) {
+                // Replaced by generated repeats
+                Ok(Type::Repeat(..)) if self.repeat.is_some() => {}
+                Ok(typed) => self.process(typed),
+                Err(error) => self.pending.push_back(Err(error)),
+            },
+            Message::Reset => self.reset(),
+            Message::Resync(pressed) => self.resync(pressed),
+        }
+        self.stamp = None;
+    }
+
+    /// Poll the typers' channels for the next event sent, round-robin so a
+    /// busy channel doesn't starve the others
+    fn poll_channels(&mut self, exec: &mut Exec<'_>) -> Poll<Sent> {
+        let start = self.next_channel % (self.device_channels.len() + 1);
+
+        for (skip, take) in [(start, usize::MAX), (0, start)] {
+            let channels = std::iter::once(&mut self.channel)
+                .chain(self.device_channels.values_mut());
+
+            for (i, channel) in channels.enumerate().skip(skip).take(take) {
+                if let Ready(sent) = Pin::new(channel).poll_next(exec) {
+                    self.next_channel = i + 1;
+                    return Ready(sent);
+                }
+            }
+        }
+        Pending
+    }
+
+    /// Generate a repeat for the held key if it's due, otherwise schedule a
+    /// wake up for when it is, returning `true` if a repeat was generated
+    fn repeat_held(&mut self, exec: &mut Exec<'_>) -> bool {
+        let (held, repeat) = match (&mut self.held, self.repeat) {
+            (Some(held), Some(repeat)) => (held, repeat),
+            _ => return false,
+        };
+        let now = Instant::now();
+
+        if now < held.next {
+            let timer = self.timer.get_or_insert_with(Timer::new);
+            timer.wake_at(held.next, exec.waker().clone());
+            return false;
+        }
+
+        let (key, mods) = (held.key, held.mods);
+        held.next = now + repeat.interval();
+        self.stamp = Some((now, held.device));
+        self.process(Type::Repeat(key, mods));
+        self.stamp = None;
+        true
+    }
+
+    /// Pass a key press to the active input method, returning `true` if it
+    /// was handled by it
+    fn input_method(&mut self, key: Key, mods: Mods, c: Option<char>) -> bool {
+        let before = self.preedit();
+        let mut commit = String::new();
+        let handled = if let Some(hangul) = &mut self.hangul {
+            hangul.press(key, mods, &mut commit)
+        } else if let Some(kana) = &mut self.kana {
+            match (key, c) {
+                (Key::LeftShift | Key::RightShift, _) => false,
+                (Key::Graph | Key::Kana, _) => false,
+                (Key::Convert, _) => {
+                    kana.toggle_katakana();
+                    true
+                }
+                (Key::NonConvert, _) => {
+                    kana.flush_romaji(&mut commit);
+                    true
+                }
+                (Key::Kanji, _) => {
+                    kana.toggle_width();
+                    true
+                }
+                (Key::Erase, _) if kana.erase() => true,
+                (_, Some(c)) => {
+                    kana.input(c, &mut commit);
+                    true
+                }
+                (_, None) => {
+                    kana.flush(&mut commit);
+                    false
+                }
+            }
+        } else {
+            return false;
+        };
+
+        self.edited(commit, before);
+        handled
+    }
+
+    /// Queue the events for text committed by an input method, and the
+    /// change in preedit text from `before`
+    fn edited(&mut self, commit: String, before: String) {
+        let preedit = self.preedit();
+        let committed = !commit.is_empty();
+
+        if committed {
+            self.push(Type::Commit(commit));
+        }
+        // Committing clears the preedit text
+        let changed = if committed {
+            !preedit.is_empty()
+        } else {
+            preedit != before
+        };
+        if changed {
+            let cursor = preedit.len();
+            self.push(Type::Preedit(preedit, cursor));
+        }
+    }
+
+    /// Queue a character generated from the layout, applying dead keys
+    fn type_char(&mut self, c: char) {
+        let dead = match self.dead.take() {
+            Some(dead) if !dead::is_dead(c) || c == dead => dead,
+            Some(dead) => {
+                dead::spacing(dead).for_each(|c| self.emit_char(c));
+                self.dead = Some(c);
+                return;
+            }
+            None if dead::is_dead(c) => {
+                self.dead = Some(c);
+                return;
+            }
+            None => return self.emit_char(c),
+        };
+
+        if c == dead || c == ' ' {
+            dead::spacing(dead).for_each(|c| self.emit_char(c));
+        } else if let Some(composed) = dead::compose(c, dead) {
+            self.emit_char(composed);
+        } else {
+            dead::spacing(dead).for_each(|c| self.emit_char(c));
+            self.emit_char(c);
+        }
+    }
+
+    /// Queue a typed character, applying compose sequences
+    fn emit_char(&mut self, c: char) {
+        let (table, sequence) = match (&self.compose, &mut self.composing) {
+            (Some(table), Some(sequence)) => (table, sequence),
+            _ => return self.push(Type::Char(c)),
+        };
+
+        sequence.push(c);
+        if let Some(result) = table.get(sequence) {
+            self.push(Type::Commit(result.to_string()));
+            self.composing = None;
+        } else if !table.is_prefix(sequence) {
+            self.push(Type::Cancel);
+            self.composing = None;
         } else {
-            Pending
+            let preedit = Type::Preedit(sequence.clone(), sequence.len());
+            self.push(preedit);
         }
     }
 }
 
-/// A keyboard typing event
-#[repr(C, packed)]
+impl Notifier for Keyboard {
+    type Event = Type;
+
+    fn poll_next(mut self: Pin<&mut Self>, exec: &mut Exec<'_>) -> Poll<Type> {
+        self.poll_event(exec).map(|event| event.typed)
+    }
+}
+
+/// Check if a key repeats while held down (modifier and lock keys don't)
+fn repeats(key: Key) -> bool {
+    !matches!(
+        key,
+        Key::LeftShift
+            | Key::RightShift
+            | Key::LeftApp
+            | Key::RightApp
+            | Key::LeftPrg
+            | Key::RightPrg
+            | Key::LeftSys
+            | Key::RightSys
+            | Key::Graph
+            | Key::Compose
+            | Key::NumLock
+            | Key::ScrollLock
+            | Key::SysLock
+            | Key::LeftAppLock
+            | Key::RightAppLock
+            | Key::LeftPrgLock
+            | Key::RightPrgLock
+            | Key::Hangul
+            | Key::Kana
+            | Key::Kanji
+    )
+}
+
+/// Get the navigation key for a numpad key, sent while NumLock is off
+fn navigation(key: Key) -> Option<Key> {
+    Some(match key {
+        Key::Num0 => Key::Insert,
+        Key::Num1 => Key::PageEnd,
+        Key::Num2 => Key::NumDown,
+        Key::Num3 => Key::PageDown,
+        Key::Num4 => Key::NumLeft,
+        Key::Num5 => Key::Clear,
+        Key::Num6 => Key::NumRight,
+        Key::Num7 => Key::PageHome,
+        Key::Num8 => Key::NumUp,
+        Key::Num9 => Key::PageUp,
+        Key::NumDecimalPoint => Key::Remove,
+        _ => return None,
+    })
+}
+
+/// A keyboard typing event, encoded as a 4-byte header followed by the text
+/// of preedit / commit events
 #[derive(Debug)]
-struct TypeInternal([u8; 4]);
+struct TypeInternal([u8; 4], String);
 
 impl TypeInternal {
+    /// Header byte for key events (never the first byte of UTF-8)
+    const KEY: u8 = 0xFF;
+    /// Header byte for text events (never the first byte of UTF-8)
+    const TEXT: u8 = 0xFE;
+
+    const COMMIT: u8 = 0;
+    const PREEDIT: u8 = 1;
+    const CANCEL: u8 = 2;
+
+    /// Key state byte for repeats (after released / pressed)
+    const REPEAT: u8 = 2;
+    /// Key state byte for lock changes (with the locks in place of mods)
+    const LOCK: u8 = 3;
+
     pub fn new(key: Key, state: bool, mods: Mods) -> Self {
-        Self([0xFF, key as u8, state as u8, mods.0])
+        Self([Self::KEY, key as u8, state as u8, mods.0], String::new())
     }
-}
 
-impl TryFrom<TypeInternal> for Type {
-    type Error = core::str::Utf8Error;
+    /// Create a text event.  Cursors past `u16::MAX` are moved back to the
+    /// last character boundary before it (or the end of the text).
+    fn text(kind: u8, text: String, cursor: usize) -> Self {
+        let cursor = u16::try_from(cursor).unwrap_or_else(|_| {
+            let mut cursor = text.len().min(usize::from(u16::MAX));
+            while !text.is_char_boundary(cursor) {
+                cursor -= 1;
+            }
+            cursor as u16
+        });
+        let [lo, hi] = cursor.to_le_bytes();
+
+        Self([Self::TEXT, kind, lo, hi], text)
+    }
+}
 
-    fn try_from(other: TypeInternal) -> Result<Self, Self::Error> {
-        let type_ = match other.0 {
-            [0xFF, key, 0, mods] => Self::Release(Key::from(key), Mods(mods)),
-            [0xFF, key, 1, mods] => Self::Press(Key::from(key), Mods(mods)),
-            utf8 => Self::Char(std::str::from_utf8(&utf8)?.chars().next().unwrap()),
+impl TypeInternal {
+    /// Decode the event, repairing it if not strict
+    pub(crate) fn decode(self, strict: bool) -> Result<Type, DecodeError> {
+        let type_ = match self.0 {
+            [0xFF, key, 0, mods] => Type::Release(Key::from(key), Mods(mods)),
+            [0xFF, key, 1, mods] => Type::Press(Key::from(key), Mods(mods)),
+            [0xFF, key, 2, mods] => Type::Repeat(Key::from(key), Mods(mods)),
+            // Bytes that are always zero
+            [0xFF, 1..=0xFF, 3, _]
+            | [0xFE, 0 | 2, 1..=0xFF, _]
+            | [0xFE, 0 | 2, _, 1..=0xFF]
+                if strict =>
+            {
+                return Err(DecodeError::InvalidHeader(self.0));
+            }
+            [0xFF, _, 3, locks] if strict && locks & !Locks::ALL != 0 => {
+                return Err(DecodeError::InvalidLocks(locks));
+            }
+            [0xFF, _, 3, locks] => Type::Lock(Locks(locks & Locks::ALL)),
+            [0xFF, _, state, _] => {
+                return Err(DecodeError::InvalidKeyState(state));
+            }
+            [0xFE, 0, _, _] => Type::Commit(self.1),
+            [0xFE, 1, lo, hi] => {
+                let mut cursor = u16::from_le_bytes([lo, hi]).into();
+                if !self.1.is_char_boundary(cursor) {
+                    if strict {
+                        return Err(DecodeError::InvalidCursor(cursor));
+                    }
+                    cursor = cursor.min(self.1.len());
+                    while !self.1.is_char_boundary(cursor) {
+                        cursor -= 1;
+                    }
+                }
+                Type::Preedit(self.1, cursor)
+            }
+            [0xFE, 2, _, _] if strict && !self.1.is_empty() => {
+                return Err(DecodeError::UnexpectedText(self.1.len()));
+            }
+            [0xFE, 2, _, _] => Type::Cancel,
+            [0xFE, kind, _, _] => {
+                return Err(DecodeError::InvalidTextKind(kind));
+            }
+            utf8 => {
+                let invalid = || DecodeError::InvalidUtf8(utf8);
+                let len = std::str::from_utf8(&utf8).map_or_else(
+                    |error| error.valid_up_to(),
+                    |text| text.len(),
+                );
+                let text = std::str::from_utf8(&utf8[..len]).unwrap();
+                let c = text.chars().next().ok_or_else(invalid)?;
+                let rest = &utf8[c.len_utf8()..];
+                if strict && rest.iter().any(|byte| *byte != 0) {
+                    return Err(DecodeError::TrailingBytes(utf8));
+                }
+                Type::Char(c)
+            }
         };
         Ok(type_)
     }
 }
 
 /// A typing event
+#[derive(Clone, Debug)]
 pub enum Type {
     Char(char),
     Press(Key, Mods),
     Release(Key, Mods),
+    /// A key held down was repeated (typematic auto-repeat)
+    Repeat(Key, Mods),
+    /// Text being composed (replacing any previous preedit text), and the
+    /// cursor position within it in bytes.  Empty text clears the preedit.
+    ///
+    /// Cursors are sent as 16 bits, so ones past byte 65535 are moved back
+    /// to the last character boundary before it (or the end of the text).
+    Preedit(String, usize),
+    /// Text typed by finishing a composition, which also clears the preedit
+    Commit(String),
+    /// The composition was cancelled, which also clears the preedit
+    Cancel,
+    /// A lock was toggled, with the new lock state
+    Lock(Locks),
 }
 
 impl From<Type> for TypeInternal {
@@ -222,7 +1268,7 @@ impl From<Type> for TypeInternal {
             Type::Char(other) => {
                 let mut unicode = [0; 4];
                 other.encode_utf8(unicode.as_mut_slice());
-                Self(unicode)
+                Self(unicode, String::new())
             }
             Type::Press(key, mods) => {
                 Self::new(key, true, mods)
@@ -230,6 +1276,527 @@ impl From<Type> for TypeInternal {
             Type::Release(key, mods) => {
                 Self::new(key, false, mods)
             }
+            Type::Repeat(key, mods) => {
+                let header = [Self::KEY, key as u8, Self::REPEAT, mods.0];
+                Self(header, String::new())
+            }
+            Type::Preedit(text, cursor) => {
+                Self::text(Self::PREEDIT, text, cursor)
+            }
+            Type::Commit(text) => Self::text(Self::COMMIT, text, 0),
+            Type::Cancel => Self::text(Self::CANCEL, String::new(), 0),
+            Type::Lock(locks) => {
+                Self([Self::KEY, 0, Self::LOCK, locks.0], String::new())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::Duration};
+
+    use super::*;
+
+    impl Keyboard {
+        /// Process events as if sent by a typer, returning the events
+        /// generated
+        fn feed(
+            &mut self,
+            typed: impl IntoIterator<Item = Type>,
+        ) -> Vec<String> {
+            for typed in typed {
+                self.receive(Sent(Message::Type(typed.into()), None, None));
+            }
+            self.pending
+                .drain(..)
+                .map(|event| match event {
+                    Ok(event) => format!("{:?}", event.typed),
+                    Err(error) => format!("{:?}", error),
+                })
+                .collect()
+        }
+
+        /// Process a message as if sent by a device's typer, returning the
+        /// events generated with their devices
+        fn send(
+            &mut self,
+            message: Message,
+            device: Option<u32>,
+        ) -> Vec<String> {
+            self.receive(Sent(message, None, device));
+            self.pending
+                .drain(..)
+                .map(|event| {
+                    let event = event.unwrap();
+                    format!("{:?} {:?}", event.device, event.typed)
+                })
+                .collect()
         }
     }
+
+    /// Send an event through the internal encoding
+    fn round_trip(typed: Type) -> Result<Type, DecodeError> {
+        TypeInternal::from(typed).decode(true)
+    }
+
+    #[test]
+    fn preedit_cursor() {
+        let cursor = |text: String, cursor| {
+            match round_trip(Type::Preedit(text, cursor)) {
+                Ok(Type::Preedit(_, cursor)) => cursor,
+                other => panic!("{:?}", other),
+            }
+        };
+
+        assert_eq!(cursor("é".repeat(4), 6), 6);
+        assert_eq!(cursor("a".repeat(70_000), 65_535), 65_535);
+        assert_eq!(cursor("a".repeat(70_000), 65_536), 65_535);
+        assert_eq!(cursor("a".repeat(70_000), 70_000), 65_535);
+        assert_eq!(cursor("é".repeat(40_000), 80_000), 65_534);
+        assert_eq!(cursor("a".repeat(10), 70_000), 10);
+    }
+
+    #[test]
+    fn sent_repeats() {
+        let mut keyboard = Keyboard::new();
+        let typed = || {
+            [
+                Type::Press(Key::A, Mods::new()),
+                Type::Repeat(Key::A, Mods::new()),
+                Type::Release(Key::A, Mods::new()),
+            ]
+        };
+
+        assert_eq!(
+            keyboard.feed(typed()),
+            [
+                "Press(A, Mods(0))",
+                "Repeat(A, Mods(0))",
+                "Release(A, Mods(0))",
+            ],
+        );
+        keyboard.set_repeat(Some(Repeat::default()));
+        assert_eq!(
+            keyboard.feed(typed()),
+            ["Press(A, Mods(0))", "Release(A, Mods(0))"],
+        );
+    }
+
+    #[cfg(feature = "term")]
+    #[test]
+    fn term_mods() {
+        let mut keyboard = Keyboard::new();
+        let mut parser = term::Parser::new();
+        let mut typed = Vec::new();
+
+        keyboard.set_layout(Some(Layout::qwerty()));
+        parser.parse(b"\x13s", |t| typed.push(t));
+        assert_eq!(
+            keyboard.feed(typed),
+            ["Press(S, Mods(32))", "Release(S, Mods(32))", "Char('s')"],
+        );
+    }
+
+    #[cfg(feature = "term")]
+    #[test]
+    fn term_kitty() {
+        let mut keyboard = Keyboard::new();
+        let mut parser = term::Parser::new();
+        let mut typed = Vec::new();
+
+        keyboard.set_layout(Some(Layout::qwerty()));
+        parser.parse(
+            b"\x1b[97;1:1u\x1b[97;1:3u\x1b[57441;2:1u\x1b[98;2:1u\
+              \x1b[98;2:3u\x1b[57441;2:3u",
+            |t| typed.push(t),
+        );
+        assert_eq!(
+            keyboard.feed(typed),
+            [
+                "Press(A, Mods(0))",
+                "Char('a')",
+                "Release(A, Mods(0))",
+                "Press(LeftShift, Mods(8))",
+                "Press(B, Mods(8))",
+                "Char('B')",
+                "Release(B, Mods(8))",
+                "Release(LeftShift, Mods(0))",
+            ],
+        );
+    }
+
+    #[test]
+    fn derived_mods() {
+        let mut keyboard = Keyboard::new();
+        let mut app = Mods::new();
+
+        app.set_app(true);
+        keyboard.set_layout(Some(Layout::qwerty()));
+        assert_eq!(
+            keyboard.feed([
+                Type::Press(Key::LeftApp, Mods::new()),
+                Type::Press(Key::S, Mods::new()),
+                Type::Release(Key::S, Mods::new()),
+                Type::Release(Key::LeftApp, Mods::new()),
+                Type::Press(Key::S, app),
+                Type::Release(Key::S, app),
+            ]),
+            [
+                "Press(LeftApp, Mods(32))",
+                "Press(S, Mods(32))",
+                "Release(S, Mods(32))",
+                "Release(LeftApp, Mods(0))",
+                "Press(S, Mods(0))",
+                "Char('s')",
+                "Release(S, Mods(0))",
+            ],
+        );
+    }
+
+    #[test]
+    fn num_lock() {
+        let mut keyboard = Keyboard::new();
+        let typed = || {
+            [
+                Type::Press(Key::Num8, Mods::new()),
+                Type::Release(Key::Num8, Mods::new()),
+            ]
+        };
+
+        keyboard.set_layout(Some(Layout::qwerty()));
+        assert!(keyboard.locks().num());
+        assert_eq!(
+            keyboard.feed(typed()),
+            ["Press(Num8, Mods(0))", "Char('8')", "Release(Num8, Mods(0))"],
+        );
+        assert_eq!(
+            keyboard.feed([Type::Press(Key::NumLock, Mods::new())]),
+            ["Press(NumLock, Mods(0))", "Lock(Locks(0))"],
+        );
+        assert_eq!(
+            keyboard.feed(typed()),
+            ["Press(NumUp, Mods(0))", "Release(NumUp, Mods(0))"],
+        );
+    }
+
+    #[test]
+    fn round_robin() {
+        let mut keyboard = Keyboard::new();
+        let typer = keyboard.typer();
+        let device = keyboard.device_typer(1);
+        let press = |key| Type::Press(key, Mods::new());
+
+        pasts::Executor::default().spawn(async move {
+            typer.send(press(Key::A)).await;
+            device.send(press(Key::B)).await;
+            let a = keyboard.next().await;
+            // The main channel is busy again, but the device is polled first
+            typer.send(press(Key::C)).await;
+            let b = keyboard.next().await;
+            let c = keyboard.next().await;
+
+            assert_eq!(
+                format!("{:?}", [a, b, c]),
+                "[Press(A, Mods(0)), Press(B, Mods(0)), Press(C, Mods(0))]",
+            );
+        });
+    }
+
+    #[test]
+    fn release_order() {
+        let mut keyboard = Keyboard::new();
+        let press = |keyboard: &mut Keyboard| {
+            for (key, device) in [
+                (Key::Z, Some(2)),
+                (Key::A, Some(2)),
+                (Key::B, Some(1)),
+                (Key::C, None),
+            ] {
+                let typed = Message::Type(Type::Press(key, Mods::new()).into());
+                keyboard.send(typed, device);
+            }
+        };
+
+        press(&mut keyboard);
+        assert_eq!(
+            keyboard.send(Message::Reset, Some(1)),
+            [
+                "None Release(C, Mods(0))",
+                "Some(1) Release(B, Mods(0))",
+                "Some(2) Release(A, Mods(0))",
+                "Some(2) Release(Z, Mods(0))",
+            ],
+        );
+
+        press(&mut keyboard);
+        let mut pressed = KeySet::new();
+        for key in [Key::W, Key::A, Key::Q] {
+            pressed.insert(key);
+        }
+        assert_eq!(
+            keyboard.send(Message::Resync(pressed), Some(1)),
+            [
+                "None Release(C, Mods(0))",
+                "Some(1) Release(B, Mods(0))",
+                "Some(2) Release(Z, Mods(0))",
+                "Some(1) Press(Q, Mods(0))",
+                "Some(1) Press(W, Mods(0))",
+            ],
+        );
+    }
+
+    #[test]
+    fn strict() {
+        let mut keyboard = Keyboard::new();
+        let preedit = || [Type::Preedit("é".into(), 1)];
+
+        assert_eq!(keyboard.feed(preedit()), ["Preedit(\"é\", 0)"]);
+        keyboard.set_strict(true);
+        assert_eq!(keyboard.feed(preedit()), ["InvalidCursor(1)"]);
+    }
+
+    #[test]
+    fn dead_keys() {
+        let mut keyboard = Keyboard::new();
+        // AZERTY, with a dead circumflex on the left bracket key
+        keyboard.set_layout(Some(Layout::azerty()));
+        let mut tap = |keys: &[Key]| {
+            let typed = keys.iter().flat_map(|key| {
+                [
+                    Type::Press(*key, Mods::new()),
+                    Type::Release(*key, Mods::new()),
+                ]
+            });
+            keyboard.feed(typed.collect::<Vec<_>>())
+        };
+
+        assert_eq!(
+            tap(&[Key::LeftBracket, Key::E]),
+            [
+                "Press(LeftBracket, Mods(0))",
+                "Release(LeftBracket, Mods(0))",
+                "Press(E, Mods(0))",
+                "Char('ê')",
+                "Release(E, Mods(0))",
+            ],
+        );
+        assert_eq!(
+            tap(&[Key::LeftBracket, Key::D]),
+            [
+                "Press(LeftBracket, Mods(0))",
+                "Release(LeftBracket, Mods(0))",
+                "Press(D, Mods(0))",
+                "Char('^')",
+                "Char('d')",
+                "Release(D, Mods(0))",
+            ],
+        );
+        assert_eq!(
+            tap(&[Key::LeftBracket, Key::Enter, Key::W]),
+            [
+                "Press(LeftBracket, Mods(0))",
+                "Release(LeftBracket, Mods(0))",
+                "Char('^')",
+                "Press(Enter, Mods(0))",
+                "Release(Enter, Mods(0))",
+                "Press(W, Mods(0))",
+                "Char('z')",
+                "Release(W, Mods(0))",
+            ],
+        );
+    }
+
+    #[test]
+    fn compose() {
+        let mut keyboard = Keyboard::new();
+        let mut table = ComposeTable::new();
+
+        table.insert("oe", "œ");
+        keyboard.set_layout(Some(Layout::qwerty()));
+        keyboard.set_compose(Some(table));
+        let mut tap = |keys: &[Key]| {
+            let typed = keys.iter().flat_map(|key| {
+                [
+                    Type::Press(*key, Mods::new()),
+                    Type::Release(*key, Mods::new()),
+                ]
+            });
+            keyboard.feed(typed.collect::<Vec<_>>())
+        };
+
+        assert_eq!(
+            tap(&[Key::Compose, Key::O, Key::E]),
+            [
+                "Press(Compose, Mods(128))",
+                "Release(Compose, Mods(128))",
+                "Press(O, Mods(128))",
+                "Preedit(\"o\", 1)",
+                "Release(O, Mods(128))",
+                "Press(E, Mods(128))",
+                "Commit(\"œ\")",
+                "Release(E, Mods(0))",
+            ],
+        );
+        // Cancelled after the escape press
+        assert_eq!(
+            tap(&[Key::Compose, Key::O, Key::Escape]),
+            [
+                "Press(Compose, Mods(128))",
+                "Release(Compose, Mods(128))",
+                "Press(O, Mods(128))",
+                "Preedit(\"o\", 1)",
+                "Release(O, Mods(128))",
+                "Press(Escape, Mods(0))",
+                "Cancel",
+                "Release(Escape, Mods(0))",
+            ],
+        );
+        // Cancelled by a character that doesn't continue a sequence
+        assert_eq!(
+            tap(&[Key::Compose, Key::X]),
+            [
+                "Press(Compose, Mods(128))",
+                "Release(Compose, Mods(128))",
+                "Press(X, Mods(128))",
+                "Cancel",
+                "Release(X, Mods(0))",
+            ],
+        );
+    }
+
+    #[test]
+    fn hangul() {
+        let mut keyboard = Keyboard::new();
+        let mut tap = |keys: &[Key]| {
+            let typed = keys.iter().flat_map(|key| {
+                [
+                    Type::Press(*key, Mods::new()),
+                    Type::Release(*key, Mods::new()),
+                ]
+            });
+            keyboard.feed(typed.collect::<Vec<_>>())
+        };
+
+        assert_eq!(tap(&[Key::Hangul]),
+            ["Press(Hangul, Mods(0))", "Release(Hangul, Mods(0))"],
+        );
+        assert_eq!(
+            tap(&[Key::G, Key::K, Key::S, Key::K]),
+            [
+                "Press(G, Mods(0))",
+                "Preedit(\"ㅎ\", 3)",
+                "Release(G, Mods(0))",
+                "Press(K, Mods(0))",
+                "Preedit(\"하\", 3)",
+                "Release(K, Mods(0))",
+                "Press(S, Mods(0))",
+                "Preedit(\"한\", 3)",
+                "Release(S, Mods(0))",
+                "Press(K, Mods(0))",
+                "Commit(\"하\")",
+                "Preedit(\"나\", 3)",
+                "Release(K, Mods(0))",
+            ],
+        );
+        // Not converted to hanja
+        assert_eq!(
+            tap(&[Key::Hanja]),
+            [
+                "Press(Hanja, Mods(0))",
+                "Commit(\"나\")",
+                "Release(Hanja, Mods(0))",
+            ],
+        );
+        // Turning it off types the syllable being composed
+        assert_eq!(
+            tap(&[Key::R, Key::Hangul, Key::R]),
+            [
+                "Press(R, Mods(0))",
+                "Preedit(\"ㄱ\", 3)",
+                "Release(R, Mods(0))",
+                "Commit(\"ㄱ\")",
+                "Press(Hangul, Mods(0))",
+                "Release(Hangul, Mods(0))",
+                "Press(R, Mods(0))",
+                "Release(R, Mods(0))",
+            ],
+        );
+    }
+
+    #[test]
+    fn repeat() {
+        let mut keyboard = Keyboard::new();
+        let (delay, interval) =
+            (Duration::from_millis(50), Duration::from_millis(20));
+        let start = Instant::now();
+
+        keyboard.set_layout(Some(Layout::qwerty()));
+        keyboard.set_repeat(Some(Repeat::new(delay, interval)));
+        assert_eq!(
+            keyboard.feed([Type::Press(Key::A, Mods::new())]),
+            ["Press(A, Mods(0))", "Char('a')"],
+        );
+
+        pasts::Executor::default().spawn(async move {
+            let first = [keyboard.next().await, keyboard.next().await];
+            assert!(start.elapsed() >= delay);
+            let second = [keyboard.next().await, keyboard.next().await];
+            assert!(start.elapsed() >= delay + interval);
+            assert_eq!(
+                format!("{:?}", [first, second]),
+                "[[Repeat(A, Mods(0)), Char('a')], \
+                 [Repeat(A, Mods(0)), Char('a')]]",
+            );
+
+            // Releasing the key stops it repeating, so the next repeat is of
+            // the key pressed after it
+            let release = Type::Release(Key::A, Mods::new());
+            assert_eq!(keyboard.feed([release]), ["Release(A, Mods(0))"]);
+            thread::sleep(delay * 2);
+            let start = Instant::now();
+            let press = Type::Press(Key::B, Mods::new());
+            assert_eq!(keyboard.feed([press]).len(), 2);
+            assert_eq!(
+                format!("{:?}", keyboard.next().await),
+                "Repeat(B, Mods(0))",
+            );
+            assert!(start.elapsed() >= delay);
+        });
+    }
+
+    #[test]
+    fn pressed() {
+        let mut keyboard = Keyboard::new();
+        let mut send = |typed: Type, device| {
+            keyboard.send(Message::Type(typed.into()), Some(device));
+            keyboard.pressed()
+        };
+        let keys = |keys: &[Key]| keys.iter().copied().collect::<KeySet>();
+
+        send(Type::Press(Key::A, Mods::new()), 1);
+        assert_eq!(send(Type::Press(Key::A, Mods::new()), 2), keys(&[Key::A]));
+        assert_eq!(
+            send(Type::Press(Key::NumRight, Mods::new()), 2),
+            keys(&[Key::A, Key::NumRight]),
+        );
+        // Still held down on the other device
+        assert_eq!(
+            send(Type::Release(Key::A, Mods::new()), 1),
+            keys(&[Key::A, Key::NumRight]),
+        );
+        assert!(keyboard.is_pressed(Key::A));
+        assert!(keyboard.is_pressed(Key::NumRight));
+        assert!(!keyboard.is_pressed(Key::B));
+        assert_eq!(keyboard.pressed_on(Some(1)), KeySet::new());
+        assert_eq!(
+            keyboard.pressed_on(Some(2)),
+            keys(&[Key::A, Key::NumRight]),
+        );
+
+        let release = Type::Release(Key::A, Mods::new());
+        keyboard.send(Message::Type(release.into()), Some(2));
+        assert!(!keyboard.is_pressed(Key::A));
+        assert_eq!(keyboard.pressed(), keys(&[Key::NumRight]));
+    }
 }
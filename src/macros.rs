@@ -0,0 +1,529 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    record::{self, FieldError},
+    repeat::Timer,
+    sequence, Key, KeySet, Keyboard, Mods, Type, Typer,
+};
+
+/// Kind of error found while parsing a [`Macro`] or [`MacroRecorder`]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum MacroErrorKind {
+    /// Not a delay in milliseconds
+    InvalidDelay(String),
+    /// Not `Press`, `Release`, `Char`, `Commit` or `Lock`
+    InvalidEvent(String),
+    /// Invalid key, modifiers, locks or quoted text
+    InvalidField(String),
+    /// Missing field after the delay or event
+    MissingField,
+    /// Not a `[MacroN]` slot header
+    InvalidSlot(String),
+    /// Event listed before the first slot header
+    MissingSlot,
+}
+
+impl From<FieldError> for MacroErrorKind {
+    fn from(error: FieldError) -> Self {
+        match error {
+            FieldError::Missing => MacroErrorKind::MissingField,
+            FieldError::Invalid(field) => MacroErrorKind::InvalidField(field),
+        }
+    }
+}
+
+/// Error parsing a [`Macro`] or [`MacroRecorder`] from its text format
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MacroError {
+    /// Line number, starting at 1
+    pub line: usize,
+    /// What went wrong
+    pub kind: MacroErrorKind,
+}
+
+impl fmt::Display for MacroError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: ", self.line)?;
+        match &self.kind {
+            MacroErrorKind::InvalidDelay(delay) => {
+                write!(f, "invalid delay `{}`", delay)
+            }
+            MacroErrorKind::InvalidEvent(event) => {
+                write!(f, "invalid event `{}`", event)
+            }
+            MacroErrorKind::InvalidField(field) => {
+                write!(f, "invalid field `{}`", field)
+            }
+            MacroErrorKind::MissingField => write!(f, "missing field"),
+            MacroErrorKind::InvalidSlot(slot) => {
+                write!(f, "invalid slot `{}`", slot)
+            }
+            MacroErrorKind::MissingSlot => write!(f, "missing slot header"),
+        }
+    }
+}
+
+impl std::error::Error for MacroError {}
+
+/// Recorded sequence of key presses and releases, typed text and lock
+/// changes, with the time between them
+///
+/// # Text Format
+/// Macros can be loaded with [`str::parse()`] and saved with
+/// [`ToString::to_string()`].  Each line lists the delay since the previous
+/// event in milliseconds, followed by the event as in the
+/// [`Recording`](crate::Recording) text format: `Press` or `Release` with
+/// the key and its modifiers as 2 hexadecimal digits, `Char` or `Commit`
+/// with the quoted text, or `Lock` with the locks as 2 hexadecimal digits.
+/// Blank lines and lines starting with `#` are ignored.
+///
+/// ```text
+/// 0   Press   LeftShift 08
+/// 120 Press   H 08
+/// 80  Release H 08
+/// 40  Release LeftShift 00
+/// 90  Commit  "ello"
+/// 70  Lock    10
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Macro(Vec<(Duration, Type)>);
+
+impl Macro {
+    /// Create an empty macro
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a key press or release, character, commit or lock change, after a
+    /// delay since the previous event, returning `false` (and not adding it)
+    /// for other events
+    pub fn push(&mut self, delay: Duration, typed: &Type) -> bool {
+        match typed {
+            Type::Press(..)
+            | Type::Release(..)
+            | Type::Char(_)
+            | Type::Commit(_)
+            | Type::Lock(_) => {}
+            Type::Repeat(..) | Type::Preedit(..) | Type::Cancel => {
+                return false
+            }
+        }
+
+        self.0.push((delay, typed.clone()));
+        true
+    }
+
+    /// Get the events, with the delay since the previous event
+    pub fn events(&self) -> impl Iterator<Item = (Duration, Type)> + '_ {
+        self.0.iter().cloned()
+    }
+
+    /// Get the number of events
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Check if there are no events
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Send the events through a typer, waiting the recorded delay before
+    /// each if `timed`, otherwise sending them as fast as they're received
+    pub async fn play(&self, typer: &Typer, timed: bool) {
+        let timer = timed.then(Timer::new);
+        let mut next = Instant::now();
+
+        for (delay, typed) in self.events() {
+            if let Some(timer) = &timer {
+                next += delay;
+                timer.sleep_until(next).await;
+            }
+            typer.send(typed).await;
+        }
+    }
+
+    /// Parse a line, returning `false` if it's blank or a comment
+    fn parse_line(&mut self, line: &str) -> Result<bool, MacroErrorKind> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(false);
+        }
+
+        let (delay, event) = line
+            .split_once(char::is_whitespace)
+            .ok_or(MacroErrorKind::MissingField)?;
+        let delay = delay
+            .parse()
+            .map(Duration::from_millis)
+            .map_err(|_| MacroErrorKind::InvalidDelay(delay.into()))?;
+        let name = event.split_whitespace().next().unwrap_or_default();
+        match name {
+            "Press" | "Release" | "Char" | "Commit" | "Lock" => {}
+            _ => return Err(MacroErrorKind::InvalidEvent(name.into())),
+        }
+
+        self.push(delay, &record::parse_event(event)?);
+        Ok(true)
+    }
+}
+
+impl FromStr for Macro {
+    type Err = MacroError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut macro_ = Self::new();
+
+        for (i, line) in s.lines().enumerate() {
+            macro_
+                .parse_line(line)
+                .map_err(|kind| MacroError { line: i + 1, kind })?;
+        }
+        Ok(macro_)
+    }
+}
+
+impl fmt::Display for Macro {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (delay, typed) in &self.0 {
+            write!(f, "{} ", delay.as_millis())?;
+            record::write_event(f, typed)?;
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Macro being recorded
+#[derive(Debug)]
+struct Recording {
+    slot: u8,
+    macro_: Macro,
+    last: Instant,
+    /// Keys pressed during the recording that haven't been released
+    held: KeySet,
+    /// If the last event recorded was a (non-modifier) key press the keyboard
+    /// typed text for, which the characters and commits that follow it are
+    /// derived from
+    derived: bool,
+}
+
+/// Macro recorder, storing a [`Macro`] per slot (`Macro1` to `Macro24` are
+/// the shift level of [`Key::F1`] to [`Key::F24`])
+///
+/// The recorder isn't driven by a [`Keyboard`]: the application records the
+/// keyboard's events, and starts and stops recordings and plays macros, such
+/// as from Shift+F-key [`Shortcut`](crate::Shortcut)s.
+///
+/// # Text Format
+/// Recorders can be loaded with [`str::parse()`] and saved with
+/// [`ToString::to_string()`], as each macro in the [`Macro`] text format,
+/// after a `[MacroN]` header line for its slot.
+///
+/// ```text
+/// [Macro1]
+/// 0  Press   H 00
+/// 70 Release H 00
+/// ```
+#[derive(Debug, Default)]
+pub struct MacroRecorder {
+    slots: BTreeMap<u8, Macro>,
+    recording: Option<Recording>,
+}
+
+impl MacroRecorder {
+    /// Create a new recorder with no macros
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the macro slot for a function key (1 for [`Key::F1`] to 24 for
+    /// [`Key::F24`])
+    pub fn slot(key: Key) -> Option<u8> {
+        let code = key as u8;
+
+        if (Key::F1 as u8..=Key::F12 as u8).contains(&code) {
+            Some(code - Key::F1 as u8 + 1)
+        } else if (Key::F13 as u8..=Key::F24 as u8).contains(&code) {
+            Some(code - Key::F13 as u8 + 13)
+        } else {
+            None
+        }
+    }
+
+    /// Start recording a macro for a slot, discarding any unfinished
+    /// recording
+    pub fn start(&mut self, slot: u8) {
+        self.recording = Some(Recording {
+            slot,
+            macro_: Macro::new(),
+            last: Instant::now(),
+            held: KeySet::new(),
+            derived: false,
+        });
+    }
+
+    /// Record an event received from a keyboard, if recording.
+    ///
+    /// If the keyboard types text for key presses (with a layout or the
+    /// Hangul input method), characters and commits right after a key press
+    /// are skipped, as they're derived from it and typed again when the
+    /// macro is played through a keyboard set up the same way.
+    pub fn record(&mut self, keyboard: &Keyboard, typed: &Type) {
+        let recording = match &mut self.recording {
+            Some(recording) => recording,
+            None => return,
+        };
+
+        match *typed {
+            Type::Press(key, _) => {
+                recording.held.insert(key);
+            }
+            Type::Release(key, _) => {
+                recording.held.remove(key);
+            }
+            Type::Char(_) | Type::Commit(_) if recording.derived => return,
+            Type::Char(_) | Type::Commit(_) | Type::Lock(_) => {}
+            Type::Repeat(..) | Type::Preedit(..) | Type::Cancel => return,
+        }
+
+        let now = Instant::now();
+        recording.macro_.push(now - recording.last, typed);
+        recording.last = now;
+        recording.derived = matches!(
+            *typed,
+            Type::Press(key, _) if !sequence::modifier(key)
+        ) && (keyboard.layout().is_some()
+            || keyboard.hangul());
+    }
+
+    /// Stop recording, storing the macro in its slot (replacing any previous
+    /// macro), and returning the slot.  Keys pressed during the recording
+    /// that are still held down are released at the end of the macro.
+    pub fn stop(&mut self) -> Option<u8> {
+        let mut recording = self.recording.take()?;
+
+        for key in recording.held.iter() {
+            let release = Type::Release(key, Mods::new());
+            recording.macro_.push(Duration::ZERO, &release);
+        }
+        self.slots.insert(recording.slot, recording.macro_);
+        Some(recording.slot)
+    }
+
+    /// Get the slot being recorded
+    pub fn recording(&self) -> Option<u8> {
+        self.recording.as_ref().map(|recording| recording.slot)
+    }
+
+    /// Get the macro in a slot
+    pub fn get(&self, slot: u8) -> Option<&Macro> {
+        self.slots.get(&slot)
+    }
+
+    /// Store a macro in a slot, returning the previous macro
+    pub fn insert(&mut self, slot: u8, macro_: Macro) -> Option<Macro> {
+        self.slots.insert(slot, macro_)
+    }
+
+    /// Remove the macro from a slot, returning it
+    pub fn remove(&mut self, slot: u8) -> Option<Macro> {
+        self.slots.remove(&slot)
+    }
+}
+
+impl FromStr for MacroRecorder {
+    type Err = MacroError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut recorder = Self::new();
+        let mut slot = None;
+
+        for (i, line) in s.lines().enumerate() {
+            let error = |kind| MacroError { line: i + 1, kind };
+            let header = line.trim();
+
+            if let Some(name) = header.strip_prefix('[') {
+                let invalid =
+                    || error(MacroErrorKind::InvalidSlot(name.into()));
+                let number = name
+                    .strip_suffix(']')
+                    .and_then(|name| name.strip_prefix("Macro"))
+                    .ok_or_else(invalid)?;
+                let number = number.parse().map_err(|_| invalid())?;
+                recorder.slots.entry(number).or_default();
+                slot = Some(number);
+                continue;
+            }
+
+            let mut macro_ = Macro::new();
+            if macro_.parse_line(line).map_err(error)? {
+                let slot =
+                    slot.ok_or_else(|| error(MacroErrorKind::MissingSlot))?;
+                recorder
+                    .slots
+                    .entry(slot)
+                    .or_default()
+                    .0
+                    .append(&mut macro_.0);
+            }
+        }
+        Ok(recorder)
+    }
+}
+
+impl fmt::Display for MacroRecorder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (slot, macro_) in &self.slots {
+            writeln!(f, "[Macro{}]", slot)?;
+            write!(f, "{}", macro_)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Layout, Locks};
+
+    fn events(macro_: &Macro) -> Vec<String> {
+        macro_
+            .events()
+            .map(|(_, typed)| format!("{:?}", typed))
+            .collect()
+    }
+
+    #[test]
+    fn record() {
+        let mut recorder = MacroRecorder::new();
+        let mut keyboard = Keyboard::new();
+
+        keyboard.set_layout(Some(Layout::qwerty()));
+        recorder.start(1);
+        for typed in [
+            Type::Char('a'),
+            Type::Press(Key::LeftShift, Mods(0x08)),
+            Type::Press(Key::H, Mods(0x08)),
+            Type::Char('H'),
+            Type::Release(Key::H, Mods(0x08)),
+            Type::Repeat(Key::H, Mods(0x08)),
+            Type::Commit("ello".into()),
+            Type::Lock(Locks(0x10)),
+        ] {
+            recorder.record(&keyboard, &typed);
+        }
+        assert_eq!(recorder.stop(), Some(1));
+        assert_eq!(
+            events(recorder.get(1).unwrap()),
+            [
+                "Char('a')",
+                "Press(LeftShift, Mods(8))",
+                "Press(H, Mods(8))",
+                "Release(H, Mods(8))",
+                "Commit(\"ello\")",
+                "Lock(Locks(16))",
+                "Release(LeftShift, Mods(0))",
+            ],
+        );
+    }
+
+    #[test]
+    fn record_typed_text() {
+        let mut recorder = MacroRecorder::new();
+        let keyboard = Keyboard::new();
+
+        // Without a layout, text is typed by the typer, such as a terminal
+        recorder.start(2);
+        for typed in [
+            Type::Press(Key::H, Mods(0x08)),
+            Type::Char('H'),
+            Type::Release(Key::H, Mods(0x08)),
+            Type::Press(Key::I, Mods(0x00)),
+            Type::Commit("i".into()),
+        ] {
+            recorder.record(&keyboard, &typed);
+        }
+        assert_eq!(recorder.stop(), Some(2));
+        assert_eq!(
+            events(recorder.get(2).unwrap()),
+            [
+                "Press(H, Mods(8))",
+                "Char('H')",
+                "Release(H, Mods(8))",
+                "Press(I, Mods(0))",
+                "Commit(\"i\")",
+                "Release(I, Mods(0))",
+            ],
+        );
+    }
+
+    #[test]
+    fn text_format() {
+        let mut macro_ = Macro::new();
+
+        for typed in [
+            Type::Press(Key::LeftShift, Mods(0x08)),
+            Type::Press(Key::A, Mods(0x87)),
+            Type::Release(Key::A, Mods(0x87)),
+            Type::Char('\''),
+            Type::Commit("한 \"x\"".into()),
+            Type::Lock(Locks(0x21)),
+        ] {
+            assert!(macro_.push(Duration::from_millis(40), &typed));
+        }
+        assert!(!macro_.push(Duration::ZERO, &Type::Cancel));
+
+        let text = macro_.to_string();
+        assert_eq!(
+            text,
+            "40 Press LeftShift 08\n\
+             40 Press A 87\n\
+             40 Release A 87\n\
+             40 Char '\\''\n\
+             40 Commit \"한 \\\"x\\\"\"\n\
+             40 Lock 21\n",
+        );
+        let parsed = text.parse::<Macro>().unwrap();
+        assert_eq!(events(&parsed), events(&macro_));
+        assert_eq!(parsed.to_string(), text);
+    }
+
+    #[test]
+    fn text_format_errors() {
+        let error = |text: &str| text.parse::<Macro>().unwrap_err().kind;
+
+        assert_eq!(
+            error("x Press A 00"),
+            MacroErrorKind::InvalidDelay("x".into())
+        );
+        assert_eq!(error("0"), MacroErrorKind::MissingField);
+        assert_eq!(error("0 Press A"), MacroErrorKind::MissingField);
+        assert_eq!(
+            error("0 Repeat A 00"),
+            MacroErrorKind::InvalidEvent("Repeat".into()),
+        );
+        assert_eq!(
+            error("0 Press Shift+A 00"),
+            MacroErrorKind::InvalidField("Shift+A".into()),
+        );
+        assert_eq!(
+            error("0 Char 'ab'"),
+            MacroErrorKind::InvalidField("'ab'".into()),
+        );
+        assert_eq!(
+            "[Macro1]\n0 Press A 00\n[Macro"
+                .parse::<MacroRecorder>()
+                .unwrap_err(),
+            MacroError {
+                line: 3,
+                kind: MacroErrorKind::InvalidSlot("Macro".into()),
+            },
+        );
+    }
+}
@@ -0,0 +1,657 @@
+use std::{
+    fmt,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    repeat::Timer,
+    wire::{self, write_varint},
+    DecodeError, Event, Key, Locks, Mods, Type, Typer,
+};
+
+/// Kind of error found while decoding a [`Recording`]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum RecordingErrorKind {
+    /// Doesn't start with the `KBRD` magic bytes
+    InvalidMagic,
+    /// Binary format version that isn't supported
+    UnsupportedVersion(u8),
+    /// Ended in the middle of an event
+    Truncated,
+    /// Invalid encoded event (or event with text too long to encode)
+    InvalidEvent(DecodeError),
+    /// Not a time in microseconds
+    InvalidTime(String),
+    /// Invalid event name, key, modifiers, locks, number or quoted text
+    InvalidField(String),
+    /// Missing field for the event
+    MissingField,
+}
+
+/// Error decoding a [`Recording`] from its binary or text format
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RecordingError {
+    /// Byte offset (binary format), or line number starting at 1 (text
+    /// format)
+    pub position: usize,
+    /// What went wrong
+    pub kind: RecordingErrorKind,
+}
+
+impl fmt::Display for RecordingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: ", self.position)?;
+        match &self.kind {
+            RecordingErrorKind::InvalidMagic => write!(f, "invalid magic"),
+            RecordingErrorKind::UnsupportedVersion(version) => {
+                write!(f, "unsupported version {}", version)
+            }
+            RecordingErrorKind::Truncated => write!(f, "truncated"),
+            RecordingErrorKind::InvalidEvent(error) => {
+                write!(f, "invalid event: {}", error)
+            }
+            RecordingErrorKind::InvalidTime(time) => {
+                write!(f, "invalid time `{}`", time)
+            }
+            RecordingErrorKind::InvalidField(field) => {
+                write!(f, "invalid field `{}`", field)
+            }
+            RecordingErrorKind::MissingField => write!(f, "missing field"),
+        }
+    }
+}
+
+impl std::error::Error for RecordingError {}
+
+/// Recorded stream of keyboard events, each with the time since the start of
+/// the recording
+///
+/// Replaying the events from a [`Keyboard`](crate::Keyboard) into a
+/// [`Typer`] for another keyboard with the same layout and input methods
+/// generates the events derived from key presses (like [`Type::Char`])
+/// again, so replay into a keyboard without them to get the same stream.
+///
+/// # Binary Format
+/// Recordings can be encoded with [`Recording::to_bytes()`] and decoded with
+/// [`Recording::from_bytes()`].  The format is an [`Encoder`](crate::Encoder)
+/// stream (the magic bytes `KBRD` and a version byte, currently 1), with
+/// each event (see [`Type::to_bytes()`]) preceded by the time since the
+/// previous event in microseconds, as an unsigned LEB128 variable-length
+/// integer.
+///
+/// # Text Format
+/// Recordings can be loaded with [`str::parse()`] and saved with
+/// [`ToString::to_string()`].  Each line lists the time since the start of
+/// the recording in microseconds, followed by the event.  Modifiers and
+/// locks are written as 2 hexadecimal digits, and text is quoted and escaped
+/// like Rust literals.  Blank lines and lines starting with `#` are ignored.
+///
+/// ```text
+/// 0      Press LeftShift 08
+/// 120000 Press H 08
+/// 120012 Char 'H'
+/// 200000 Release H 08
+/// 250000 Lock 10
+/// 310000 Preedit 3 "한"
+/// 390000 Commit "한"
+/// 390000 Cancel
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Recording {
+    events: Vec<(Duration, Type)>,
+    /// When the first event recorded with [`Recording::record()`] or
+    /// [`Recording::record_event()`] happened
+    start: Option<Instant>,
+}
+
+impl Recording {
+    /// Create an empty recording
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record an event now, timed from the first event recorded
+    pub fn record(&mut self, typed: &Type) {
+        self.record_at(Instant::now(), typed.clone());
+    }
+
+    /// Record an event at the time it happened, timed from the first event
+    /// recorded
+    pub fn record_event(&mut self, event: &Event) {
+        self.record_at(event.time, event.typed.clone());
+    }
+
+    /// Add an event at a time since the start of the recording (which is
+    /// raised to the time of the last event, if earlier)
+    pub fn push(&mut self, time: Duration, typed: Type) {
+        let last = self.events.last().map_or(Duration::ZERO, |(t, _)| *t);
+
+        self.events.push((time.max(last), typed));
+    }
+
+    /// Record an event at a time, timed from the first event recorded
+    fn record_at(&mut self, time: Instant, typed: Type) {
+        let start = *self.start.get_or_insert(time);
+
+        self.push(time.saturating_duration_since(start), typed);
+    }
+
+    /// Get the events, with the time since the start of the recording
+    pub fn events(&self) -> &[(Duration, Type)] {
+        &self.events
+    }
+
+    /// Get the time of the last event
+    pub fn duration(&self) -> Duration {
+        self.events.last().map_or(Duration::ZERO, |(t, _)| *t)
+    }
+
+    /// Send the events through a typer, at `speed` times the recorded speed
+    /// (1.0 for real time, or [`f64::INFINITY`] to send them as fast as
+    /// they're received)
+    pub async fn play(&self, typer: &Typer, speed: f64) {
+        let timer = (speed.is_finite() && speed > 0.0).then(Timer::new);
+        let start = Instant::now();
+
+        for (time, typed) in &self.events {
+            if let Some(timer) = &timer {
+                let delay = time.as_secs_f64() / speed;
+                let deadline = start + Duration::from_secs_f64(delay);
+                timer.sleep_until(deadline).await;
+            }
+            typer.send(typed.clone()).await;
+        }
+    }
+
+    /// Start a step-by-step playback
+    pub fn playback(&self) -> Playback<'_> {
+        Playback {
+            recording: self,
+            next: 0,
+        }
+    }
+
+    /// Encode in the binary format, failing with
+    /// [`RecordingErrorKind::InvalidEvent`] (at the byte offset the event
+    /// would be encoded at) if an event's text is too long to decode
+    pub fn to_bytes(&self) -> Result<Vec<u8>, RecordingError> {
+        let mut bytes = Vec::new();
+        let mut last = Duration::ZERO;
+
+        wire::write_header(&mut bytes);
+        for (time, typed) in &self.events {
+            let delta = time.saturating_sub(last).as_micros();
+            let start = bytes.len();
+
+            last = *time;
+            write_varint(&mut bytes, u64::try_from(delta).unwrap_or(u64::MAX));
+            wire::encode(typed, &mut bytes).map_err(|e| {
+                error(start, RecordingErrorKind::InvalidEvent(e))
+            })?;
+        }
+        Ok(bytes)
+    }
+
+    /// Decode from the binary format, failing on events that could be
+    /// repaired (see [`DecodeError`](crate::DecodeError))
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, RecordingError> {
+        Self::decode(bytes, true)
+    }
+
+    /// Decode from the binary format, repairing the events that can be
+    /// (see [`DecodeError`](crate::DecodeError))
+    pub fn from_bytes_lenient(bytes: &[u8]) -> Result<Self, RecordingError> {
+        Self::decode(bytes, false)
+    }
+
+    /// Decode from the binary format, strictly or not
+    fn decode(bytes: &[u8], strict: bool) -> Result<Self, RecordingError> {
+        let mut rest = bytes;
+        let mut recording = Self::new();
+        let mut time = Duration::ZERO;
+
+        wire::read_header(&mut rest).map_err(|e| {
+            match wire::decode_error(e) {
+                DecodeError::Truncated => {
+                    error(bytes.len(), RecordingErrorKind::Truncated)
+                }
+                DecodeError::UnsupportedVersion(version) => {
+                    let kind = RecordingErrorKind::UnsupportedVersion(version);
+                    error(wire::MAGIC.len(), kind)
+                }
+                _ => error(0, RecordingErrorKind::InvalidMagic),
+            }
+        })?;
+
+        while !rest.is_empty() {
+            let start = bytes.len() - rest.len();
+            let event = wire::read_varint(&mut rest).and_then(|delta| {
+                let typed = wire::read(&mut rest, strict)?
+                    .ok_or(DecodeError::Truncated)?;
+                Ok((delta, typed))
+            });
+            let (delta, typed) =
+                event.map_err(|e| match wire::decode_error(e) {
+                    DecodeError::Truncated => {
+                        error(bytes.len(), RecordingErrorKind::Truncated)
+                    }
+                    e => error(start, RecordingErrorKind::InvalidEvent(e)),
+                })?;
+
+            time = time.saturating_add(Duration::from_micros(delta));
+            recording.events.push((time, typed));
+        }
+        Ok(recording)
+    }
+}
+
+impl FromStr for Recording {
+    type Err = RecordingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut recording = Self::new();
+
+        for (i, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (time, typed) =
+                parse_line(line).map_err(|kind| RecordingError {
+                    position: i + 1,
+                    kind,
+                })?;
+            recording.push(time, typed);
+        }
+        Ok(recording)
+    }
+}
+
+impl fmt::Display for Recording {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (time, typed) in &self.events {
+            write!(f, "{} ", time.as_micros())?;
+            write_event(f, typed)?;
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Step-by-step playback of a [`Recording`]
+#[derive(Debug)]
+pub struct Playback<'a> {
+    recording: &'a Recording,
+    next: usize,
+}
+
+impl Playback<'_> {
+    /// Get the next event to send, with its time since the start of the
+    /// recording
+    pub fn peek(&self) -> Option<&(Duration, Type)> {
+        self.recording.events.get(self.next)
+    }
+
+    /// Send the next event through a typer, returning it (or `None` if the
+    /// playback is finished)
+    pub async fn step(&mut self, typer: &Typer) -> Option<&(Duration, Type)> {
+        let event = self.recording.events.get(self.next)?;
+
+        self.next += 1;
+        typer.send(event.1.clone()).await;
+        Some(event)
+    }
+
+    /// Get the number of events sent so far
+    pub fn position(&self) -> usize {
+        self.next
+    }
+}
+
+/// Create an error at a byte offset in the binary format
+fn error(position: usize, kind: RecordingErrorKind) -> RecordingError {
+    RecordingError { position, kind }
+}
+
+/// Error parsing the fields of an event in the text format
+#[derive(Debug)]
+pub(crate) enum FieldError {
+    /// Missing field for the event
+    Missing,
+    /// Invalid event name, key, modifiers, locks, number or quoted text
+    Invalid(String),
+}
+
+impl From<FieldError> for RecordingErrorKind {
+    fn from(error: FieldError) -> Self {
+        match error {
+            FieldError::Missing => RecordingErrorKind::MissingField,
+            FieldError::Invalid(field) => {
+                RecordingErrorKind::InvalidField(field)
+            }
+        }
+    }
+}
+
+/// Parse a line of the text format
+fn parse_line(line: &str) -> Result<(Duration, Type), RecordingErrorKind> {
+    let (time, rest) = split_field(line)?;
+    let time = time
+        .parse()
+        .map(Duration::from_micros)
+        .map_err(|_| RecordingErrorKind::InvalidTime(time.into()))?;
+
+    Ok((time, parse_event(rest)?))
+}
+
+/// Parse an event in the text format (a line without its time)
+pub(crate) fn parse_event(line: &str) -> Result<Type, FieldError> {
+    let invalid = |field: &str| FieldError::Invalid(field.into());
+    let (event, rest) = split_field(line)?;
+    let key_mods = |rest| -> Result<(Key, Mods), FieldError> {
+        let (key, mods) = split_field(rest)?;
+        let key = Key::from_name(key).ok_or_else(|| invalid(key))?;
+
+        Ok((key, Mods(hex(mods)?)))
+    };
+
+    Ok(match event {
+        "Char" => {
+            let c = unquote(rest, '\'').ok_or_else(|| invalid(rest))?;
+            let mut chars = c.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Type::Char(c),
+                _ => return Err(invalid(rest)),
+            }
+        }
+        "Press" => {
+            let (key, mods) = key_mods(rest)?;
+            Type::Press(key, mods)
+        }
+        "Release" => {
+            let (key, mods) = key_mods(rest)?;
+            Type::Release(key, mods)
+        }
+        "Repeat" => {
+            let (key, mods) = key_mods(rest)?;
+            Type::Repeat(key, mods)
+        }
+        "Preedit" => {
+            let (cursor, text) = split_field(rest)?;
+            let cursor = cursor.parse().map_err(|_| invalid(cursor))?;
+            let text = unquote(text, '"').ok_or_else(|| invalid(text))?;
+            Type::Preedit(text, cursor)
+        }
+        "Commit" => {
+            Type::Commit(unquote(rest, '"').ok_or_else(|| invalid(rest))?)
+        }
+        "Cancel" if rest.is_empty() => Type::Cancel,
+        "Lock" => match hex(rest)? {
+            locks if locks & !Locks::ALL != 0 => return Err(invalid(rest)),
+            locks => Type::Lock(Locks(locks)),
+        },
+        event => return Err(invalid(event)),
+    })
+}
+
+/// Write an event in the text format (a line without its time or newline)
+pub(crate) fn write_event(
+    f: &mut fmt::Formatter<'_>,
+    typed: &Type,
+) -> fmt::Result {
+    match typed {
+        Type::Char(c) => write!(f, "Char {:?}", c),
+        Type::Press(key, mods) => write!(f, "Press {:?} {:02X}", key, mods.0),
+        Type::Release(key, mods) => {
+            write!(f, "Release {:?} {:02X}", key, mods.0)
+        }
+        Type::Repeat(key, mods) => {
+            write!(f, "Repeat {:?} {:02X}", key, mods.0)
+        }
+        Type::Preedit(text, cursor) => {
+            write!(f, "Preedit {} {:?}", cursor, text)
+        }
+        Type::Commit(text) => write!(f, "Commit {:?}", text),
+        Type::Cancel => write!(f, "Cancel"),
+        Type::Lock(locks) => write!(f, "Lock {:02X}", locks.0),
+    }
+}
+
+/// Parse modifiers or locks written as 2 hexadecimal digits
+fn hex(field: &str) -> Result<u8, FieldError> {
+    if field.is_empty() {
+        return Err(FieldError::Missing);
+    }
+    u8::from_str_radix(field, 16).map_err(|_| FieldError::Invalid(field.into()))
+}
+
+/// Split the first whitespace-separated field from the rest of a line
+fn split_field(line: &str) -> Result<(&str, &str), FieldError> {
+    let line = line.trim_start();
+    if line.is_empty() {
+        return Err(FieldError::Missing);
+    }
+    let end = line.find(char::is_whitespace).unwrap_or(line.len());
+
+    Ok((&line[..end], line[end..].trim()))
+}
+
+/// Parse text quoted and escaped like a Rust literal (as written by
+/// [`Debug`])
+fn unquote(text: &str, quote: char) -> Option<String> {
+    let mut chars = text.strip_prefix(quote)?.strip_suffix(quote)?.chars();
+    let mut unquoted = String::new();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unquoted.push(c);
+            continue;
+        }
+        unquoted.push(match chars.next()? {
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            '0' => '\0',
+            '\\' => '\\',
+            '\'' => '\'',
+            '"' => '"',
+            'u' => {
+                let rest = chars.as_str().strip_prefix('{')?;
+                let (hex, rest) = rest.split_once('}')?;
+                chars = rest.chars();
+                char::from_u32(u32::from_str_radix(hex, 16).ok()?)?
+            }
+            _ => return None,
+        });
+    }
+    Some(unquoted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recording() -> Recording {
+        let mut recording = Recording::new();
+
+        for (time, typed) in [
+            (0, Type::Press(Key::LeftShift, Mods(0x08))),
+            (120_000, Type::Press(Key::H, Mods(0x88))),
+            (120_012, Type::Char('H')),
+            (200_000, Type::Repeat(Key::H, Mods(0x08))),
+            (250_000, Type::Lock(Locks(0x10))),
+            (310_000, Type::Preedit("한".into(), 3)),
+            (390_000, Type::Commit("한\n".into())),
+            (390_000, Type::Cancel),
+        ] {
+            recording.push(Duration::from_micros(time), typed);
+        }
+        recording
+    }
+
+    fn events(recording: &Recording) -> String {
+        format!("{:?}", recording.events())
+    }
+
+    #[test]
+    fn record_event() {
+        let start = Instant::now();
+        let mut recording = Recording::new();
+        let mut event = |millis, typed| {
+            let mut event = Event::new(typed);
+            event.time = start + Duration::from_millis(millis);
+            recording.record_event(&event);
+        };
+
+        event(100, Type::Press(Key::A, Mods(0)));
+        event(150, Type::Release(Key::A, Mods(0)));
+        // Out of order, so raised to the time of the last event
+        event(120, Type::Char('a'));
+        assert_eq!(
+            recording
+                .events()
+                .iter()
+                .map(|(time, _)| time.as_millis())
+                .collect::<Vec<_>>(),
+            [0, 50, 50],
+        );
+    }
+
+    #[test]
+    fn text_format() {
+        let recording = recording();
+        let text = recording.to_string();
+
+        assert_eq!(
+            text,
+            "0 Press LeftShift 08\n\
+             120000 Press H 88\n\
+             120012 Char 'H'\n\
+             200000 Repeat H 08\n\
+             250000 Lock 10\n\
+             310000 Preedit 3 \"한\"\n\
+             390000 Commit \"한\\n\"\n\
+             390000 Cancel\n",
+        );
+        assert_eq!(events(&text.parse().unwrap()), events(&recording));
+    }
+
+    #[test]
+    fn text_format_errors() {
+        let error = |text: &str| text.parse::<Recording>().unwrap_err();
+        let kind = |text: &str| error(text).kind;
+
+        assert_eq!(
+            kind("x Cancel"),
+            RecordingErrorKind::InvalidTime("x".into())
+        );
+        assert_eq!(kind("0 Press A"), RecordingErrorKind::MissingField);
+        assert_eq!(kind("0 Lock"), RecordingErrorKind::MissingField);
+        assert_eq!(
+            kind("0 Lock FF"),
+            RecordingErrorKind::InvalidField("FF".into()),
+        );
+        assert_eq!(
+            kind("0 Lock 40"),
+            RecordingErrorKind::InvalidField("40".into()),
+        );
+        assert_eq!(
+            kind("0 Cancel now"),
+            RecordingErrorKind::InvalidField("Cancel".into()),
+        );
+        assert_eq!(error("0 Cancel\n\n# x\n0 Char 'ab'").position, 4);
+    }
+
+    #[test]
+    fn binary_format() {
+        let recording = recording();
+        let bytes = recording.to_bytes().unwrap();
+
+        assert_eq!(bytes[..5], *b"KBRD\x01");
+        assert_eq!(
+            events(&Recording::from_bytes(&bytes).unwrap()),
+            events(&recording),
+        );
+        for len in 5..bytes.len() {
+            match Recording::from_bytes(&bytes[..len]) {
+                Ok(prefix) => assert!(prefix.events().len() < 8),
+                Err(error) => {
+                    assert_eq!(
+                        error,
+                        RecordingError {
+                            position: len,
+                            kind: RecordingErrorKind::Truncated,
+                        }
+                    );
+                }
+            }
+        }
+        assert_eq!(
+            Recording::from_bytes(b"KBRD").unwrap_err(),
+            RecordingError {
+                position: 4,
+                kind: RecordingErrorKind::Truncated,
+            },
+        );
+    }
+
+    #[test]
+    fn binary_format_errors() {
+        let error = |bytes: &[u8]| Recording::from_bytes(bytes).unwrap_err();
+
+        assert_eq!(error(b"KBEV\x01").kind, RecordingErrorKind::InvalidMagic);
+        assert_eq!(
+            error(b"KBRD\x02").kind,
+            RecordingErrorKind::UnsupportedVersion(2),
+        );
+        assert_eq!(
+            error(b"KBRD\x01\x00\xFF\x00\x03\x10\x00\xFF\x00\x03\xFF"),
+            RecordingError {
+                position: 10,
+                kind: RecordingErrorKind::InvalidEvent(
+                    DecodeError::InvalidLocks(0xFF),
+                ),
+            },
+        );
+        let locks = b"KBRD\x01\x00\xFF\x00\x03\xFF";
+        assert_eq!(
+            error(locks).kind,
+            RecordingErrorKind::InvalidEvent(DecodeError::InvalidLocks(0xFF)),
+        );
+        assert_eq!(
+            events(&Recording::from_bytes_lenient(locks).unwrap()),
+            "[(0ns, Lock(Locks(63)))]",
+        );
+        assert_eq!(
+            error(&[&b"KBRD\x01"[..], &[0xFF; 10], &[0x01]].concat()),
+            RecordingError {
+                position: 5,
+                kind: RecordingErrorKind::InvalidEvent(
+                    DecodeError::InvalidLength,
+                ),
+            },
+        );
+    }
+
+    #[test]
+    fn text_too_long() {
+        let mut recording = Recording::new();
+        let long = "x".repeat(0x10001);
+
+        recording.push(Duration::ZERO, Type::Commit(long[1..].into()));
+        let bytes = recording.to_bytes().unwrap();
+        assert_eq!(Recording::from_bytes(&bytes).unwrap().events().len(), 1);
+
+        recording.push(Duration::ZERO, Type::Preedit(long, 0));
+        assert_eq!(
+            recording.to_bytes().unwrap_err(),
+            RecordingError {
+                position: bytes.len(),
+                kind: RecordingErrorKind::InvalidEvent(
+                    DecodeError::InvalidLength,
+                ),
+            },
+        );
+    }
+}
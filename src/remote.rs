@@ -0,0 +1,570 @@
+//! Remote keyboards over TCP or Unix sockets.
+//!
+//! A [`Client`] forwards the key events of a [`Keyboard`] to a [`Server`],
+//! which sends them through a [`Typer`] to a keyboard in another process or
+//! on another machine.
+//!
+//! # Protocol
+//! The client starts by sending the [`Encoder`] stream header, which the
+//! server checks and sends back.  After that, the client sends messages,
+//! each starting with a tag byte:
+//!
+//!  - `00`: heartbeat, sent when no event has been sent for a while
+//!  - `01`: event, followed by a [`Type`] in the wire format (see
+//!    [`Type::to_bytes()`])
+//!
+//! When the client disconnects, or misses its heartbeats, the server
+//! releases the keys it left held down.  Invalid messages (including text
+//! over 64 KiB) disconnect the client.
+//!
+//! # Blocking
+//! Sockets are read and written in blocking mode, so the async functions
+//! here block the thread running them, and should be run on an executor
+//! dedicated to them.  [`Server::listen()`] and [`Server::listen_unix()`]
+//! serve one client at a time: other clients wait to be accepted until the
+//! current one disconnects.  To serve clients concurrently, accept the
+//! connections and run [`Server::serve()`] for each on its own thread, with
+//! a server created from a clone of the [`Typer`].
+
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::{
+    io::{Error, ErrorKind, Read, Result, Write},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    time::{Duration, Instant},
+};
+
+use pasts::prelude::*;
+
+use crate::{
+    repeat::Timer, wire, Decoder, Encoder, KeySet, Keyboard, Mods, Type, Typer,
+};
+
+/// Tag byte of heartbeat messages
+const HEARTBEAT: u8 = 0x00;
+/// Tag byte of event messages
+const EVENT: u8 = 0x01;
+
+/// Socket connection a remote keyboard can be used over
+pub trait Connection: Read + Write {
+    /// Set the time reads wait before failing, or `None` to wait forever
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<()>;
+}
+
+impl Connection for TcpStream {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<()> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+}
+
+#[cfg(unix)]
+impl Connection for UnixStream {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<()> {
+        UnixStream::set_read_timeout(self, timeout)
+    }
+}
+
+/// Server receiving events from remote [`Client`]s, and sending them through
+/// a [`Typer`]
+///
+/// Reading blocks the current thread, so the server should usually be run
+/// on an executor dedicated to it (see [Blocking](self#blocking)).
+#[derive(Debug)]
+pub struct Server {
+    typer: Typer,
+    timeout: Duration,
+}
+
+impl Server {
+    /// Create a new server sending events through a typer, which disconnects
+    /// clients that don't send anything for 3 seconds
+    pub fn new(typer: Typer) -> Self {
+        Self {
+            typer,
+            timeout: Duration::from_secs(3),
+        }
+    }
+
+    /// Set the time a client can go without sending anything (including
+    /// heartbeats) before it's disconnected
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Get the time a client can go without sending anything
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Accept TCP connections one at a time, serving each until it
+    /// disconnects, blocking the current thread.  Errors on a connection
+    /// close it, without stopping the server.
+    pub async fn listen(&self, listener: &TcpListener) -> Result<()> {
+        loop {
+            let (connection, _) = listener.accept()?;
+            connection.set_nodelay(true)?;
+            let _ = self.serve(connection).await;
+        }
+    }
+
+    /// Accept Unix socket connections one at a time, serving each until it
+    /// disconnects, blocking the current thread.  Errors on a connection
+    /// close it, without stopping the server.
+    #[cfg(unix)]
+    pub async fn listen_unix(&self, listener: &UnixListener) -> Result<()> {
+        loop {
+            let (connection, _) = listener.accept()?;
+            let _ = self.serve(connection).await;
+        }
+    }
+
+    /// Serve a client's connection until it disconnects, then release the
+    /// keys it left held down
+    pub async fn serve<C: Connection>(&self, mut connection: C) -> Result<()> {
+        let mut held = KeySet::new();
+
+        connection.set_read_timeout(Some(self.timeout))?;
+        Decoder::new(&mut connection)?;
+        Encoder::new(&mut connection)?.flush()?;
+
+        let result = self.receive(&mut connection, &mut held).await;
+        for key in held.iter() {
+            self.typer.send(Type::Release(key, Mods::new())).await;
+        }
+        result
+    }
+
+    /// Receive messages until the client disconnects, keeping track of the
+    /// keys held down
+    async fn receive<C: Connection>(
+        &self,
+        connection: &mut C,
+        held: &mut KeySet,
+    ) -> Result<()> {
+        loop {
+            let mut tag = [0];
+            match connection.read_exact(&mut tag) {
+                Ok(()) => {}
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(()),
+                Err(e) if is_timeout(&e) => {
+                    return Err(Error::new(ErrorKind::TimedOut, "no heartbeat"))
+                }
+                Err(e) => return Err(e),
+            }
+
+            let typed = match tag[0] {
+                HEARTBEAT => continue,
+                EVENT => wire::read(connection, true)?
+                    .ok_or_else(|| Error::from(ErrorKind::UnexpectedEof))?,
+                tag => {
+                    let message = format!("invalid message tag {:02X}", tag);
+                    return Err(Error::new(ErrorKind::InvalidData, message));
+                }
+            };
+            match typed {
+                Type::Press(key, _) => held.insert(key),
+                Type::Release(key, _) => held.remove(key),
+                _ => false,
+            };
+            self.typer.send(typed).await;
+        }
+    }
+}
+
+/// Client sending the key events of a [`Keyboard`] to a remote [`Server`]
+#[derive(Debug)]
+pub struct Client<C: Connection> {
+    connection: C,
+    heartbeat: Duration,
+}
+
+impl Client<TcpStream> {
+    /// Connect to a server over TCP
+    pub fn connect(address: impl ToSocketAddrs) -> Result<Self> {
+        let connection = TcpStream::connect(address)?;
+
+        connection.set_nodelay(true)?;
+        Self::new(connection)
+    }
+}
+
+#[cfg(unix)]
+impl Client<UnixStream> {
+    /// Connect to a server over a Unix socket
+    pub fn connect_unix(path: impl AsRef<std::path::Path>) -> Result<Self> {
+        Self::new(UnixStream::connect(path)?)
+    }
+}
+
+impl<C: Connection> Client<C> {
+    /// Create a new client over a connection to a server, doing the
+    /// handshake.  Heartbeats are sent every second by default.
+    pub fn new(mut connection: C) -> Result<Self> {
+        Encoder::new(&mut connection)?.flush()?;
+        Decoder::new(&mut connection)?;
+
+        Ok(Self {
+            connection,
+            heartbeat: Duration::from_secs(1),
+        })
+    }
+
+    /// Set the time between heartbeats sent while no events are sent, which
+    /// should be shorter than the server's timeout
+    pub fn set_heartbeat(&mut self, heartbeat: Duration) {
+        self.heartbeat = heartbeat;
+    }
+
+    /// Get the time between heartbeats sent while no events are sent
+    pub fn heartbeat(&self) -> Duration {
+        self.heartbeat
+    }
+
+    /// Send an event, failing with [`ErrorKind::InvalidInput`] if its text
+    /// is too long for the server to decode
+    pub fn send(&mut self, typed: &Type) -> Result<()> {
+        let mut message = vec![EVENT];
+
+        message.extend_from_slice(&wire::checked(typed)?);
+        self.connection.write_all(&message)?;
+        self.connection.flush()
+    }
+
+    /// Send a heartbeat
+    pub fn send_heartbeat(&mut self) -> Result<()> {
+        self.connection.write_all(&[HEARTBEAT])?;
+        self.connection.flush()
+    }
+
+    /// Forward a keyboard's key presses, releases and lock changes until the
+    /// connection fails, sending heartbeats while there are none.
+    ///
+    /// Keys held down are repeated by the remote keyboard.  If the keyboard
+    /// types text for key presses (with a layout or the Hangul input
+    /// method), the text is typed by the remote keyboard's layout (and input
+    /// methods) instead of being forwarded.  Otherwise, the characters and
+    /// commits sent to the keyboard (such as by a terminal) are forwarded
+    /// too.  Preedit text isn't forwarded.  Writing blocks the current
+    /// thread, so this should usually be run on an executor dedicated to the
+    /// connection.
+    pub async fn forward(&mut self, keyboard: &mut Keyboard) -> Result<()> {
+        let mut idle = Idle {
+            keyboard,
+            timer: Timer::new(),
+            deadline: Instant::now() + self.heartbeat,
+        };
+
+        loop {
+            // Text isn't forwarded if it's typed for key presses
+            let text =
+                idle.keyboard.layout().is_none() && !idle.keyboard.hangul();
+
+            match idle.next().await {
+                Some(
+                    typed @ (Type::Press(..)
+                    | Type::Release(..)
+                    | Type::Lock(_)),
+                ) => self.send(&typed)?,
+                Some(typed @ (Type::Char(_) | Type::Commit(_))) if text => {
+                    self.send(&typed)?
+                }
+                Some(_) => continue,
+                None => self.send_heartbeat()?,
+            }
+            idle.deadline = Instant::now() + self.heartbeat;
+        }
+    }
+
+    /// Get the connection
+    pub fn get_ref(&self) -> &C {
+        &self.connection
+    }
+
+    /// Get the connection back
+    pub fn into_inner(self) -> C {
+        self.connection
+    }
+}
+
+/// Notifier yielding a keyboard's events, or `None` once a deadline passes
+/// without one
+struct Idle<'a> {
+    keyboard: &'a mut Keyboard,
+    timer: Timer,
+    deadline: Instant,
+}
+
+impl Notifier for Idle<'_> {
+    type Event = Option<Type>;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        exec: &mut Exec<'_>,
+    ) -> Poll<Self::Event> {
+        if let Ready(typed) = Pin::new(&mut *self.keyboard).poll_next(exec) {
+            return Ready(Some(typed));
+        }
+        if Instant::now() >= self.deadline {
+            return Ready(None);
+        }
+        self.timer.wake_at(self.deadline, exec.waker().clone());
+        Pending
+    }
+}
+
+/// Check if an error is from a read timing out
+fn is_timeout(error: &Error) -> bool {
+    matches!(error.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::mpsc, thread};
+
+    use pasts::Executor;
+
+    use super::*;
+    use crate::{DecodeError, Key, Layout, Locks};
+
+    /// Get the next events a keyboard yields
+    fn next(keyboard: Keyboard, count: usize) -> (Keyboard, Vec<String>) {
+        let (sender, receiver) = mpsc::channel();
+
+        Executor::default().spawn(async move {
+            let mut keyboard = keyboard;
+            let mut events = Vec::new();
+            for _ in 0..count {
+                events.push(format!("{:?}", keyboard.next().await));
+            }
+            sender.send((keyboard, events)).unwrap();
+        });
+        receiver.recv().unwrap()
+    }
+
+    /// Serve a connection on another thread, once it's accepted
+    fn serve<C: Connection + 'static>(
+        server: Server,
+        accept: impl FnOnce() -> C + Send + 'static,
+    ) -> mpsc::Receiver<Result<()>> {
+        let (sender, receiver) = mpsc::channel();
+
+        thread::spawn(move || {
+            Executor::default().spawn(async move {
+                let result = server.serve(accept()).await;
+                sender.send(result).unwrap();
+            });
+        });
+        receiver
+    }
+
+    /// Get the decoding error a server failed with
+    fn decode_error(result: Result<()>) -> DecodeError {
+        let error = result.unwrap_err();
+
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        *error.into_inner().unwrap().downcast().unwrap()
+    }
+
+    fn press(key: Key) -> Type {
+        Type::Press(key, Mods::new())
+    }
+
+    #[test]
+    fn listen() {
+        let keyboard = Keyboard::new();
+        let server = Server::new(keyboard.typer());
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+
+        thread::spawn(move || {
+            Executor::default().spawn(async move {
+                server.listen(&listener).await.unwrap();
+            });
+        });
+        // The second client waits for the first to be served
+        thread::spawn(move || {
+            for key in [Key::A, Key::B] {
+                let mut client = Client::connect(address).unwrap();
+                client.send(&press(key)).unwrap();
+                client.send(&press(Key::C)).unwrap();
+                client.send(&Type::Release(Key::C, Mods::new())).unwrap();
+            }
+        });
+
+        let (_, events) = next(keyboard, 8);
+        assert_eq!(
+            events,
+            [
+                "Press(A, Mods(0))",
+                "Press(C, Mods(0))",
+                "Release(C, Mods(0))",
+                // Released when the first client disconnects
+                "Release(A, Mods(0))",
+                "Press(B, Mods(0))",
+                "Press(C, Mods(0))",
+                "Release(C, Mods(0))",
+                "Release(B, Mods(0))",
+            ],
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unix() {
+        let path = std::env::temp_dir()
+            .join(format!("kbrd-remote-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        let keyboard = Keyboard::new();
+        let served = serve(Server::new(keyboard.typer()), move || {
+            listener.accept().unwrap().0
+        });
+
+        let mut client = Client::connect_unix(&path).unwrap();
+        client.send(&press(Key::A)).unwrap();
+        client.send(&Type::Commit("한".into())).unwrap();
+        client.send(&Type::Lock(Locks::new())).unwrap();
+        drop(client);
+
+        let (_, events) = next(keyboard, 4);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            events,
+            [
+                "Press(A, Mods(0))",
+                "Commit(\"한\")",
+                "Lock(Locks(0))",
+                "Release(A, Mods(0))",
+            ],
+        );
+        served.recv().unwrap().unwrap();
+    }
+
+    #[test]
+    fn heartbeat() {
+        let keyboard = Keyboard::new();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let mut server = Server::new(keyboard.typer());
+        server.set_timeout(Duration::from_millis(200));
+        let served = serve(server, move || listener.accept().unwrap().0);
+
+        let mut client = Client::connect(address).unwrap();
+        client.send(&press(Key::A)).unwrap();
+        // Heartbeats keep the client connected past the timeout
+        for _ in 0..6 {
+            thread::sleep(Duration::from_millis(50));
+            client.send_heartbeat().unwrap();
+        }
+        client.send(&press(Key::B)).unwrap();
+
+        let (_, events) = next(keyboard, 4);
+        assert_eq!(
+            events,
+            [
+                "Press(A, Mods(0))",
+                "Press(B, Mods(0))",
+                "Release(A, Mods(0))",
+                "Release(B, Mods(0))",
+            ],
+        );
+        let error = served.recv().unwrap().unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::TimedOut);
+        drop(client);
+    }
+
+    #[test]
+    fn invalid() {
+        let keyboard = Keyboard::new();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let accept =
+            |listener: TcpListener| move || listener.accept().unwrap().0;
+
+        // Wrong stream header
+        let served = serve(
+            Server::new(keyboard.typer()),
+            accept(listener.try_clone().unwrap()),
+        );
+        let mut connection = TcpStream::connect(address).unwrap();
+        connection.write_all(b"KBEV\x01").unwrap();
+        assert_eq!(
+            decode_error(served.recv().unwrap()),
+            DecodeError::InvalidMagic,
+        );
+
+        // Text over 64 KiB
+        let served = serve(Server::new(keyboard.typer()), accept(listener));
+        let mut connection = TcpStream::connect(address).unwrap();
+        Encoder::new(&mut connection).unwrap();
+        Decoder::new(&mut connection).unwrap();
+        connection
+            .write_all(&[EVENT, 0xFE, 0x00, 0x00, 0x00, 0x81, 0x80, 0x04])
+            .unwrap();
+        assert_eq!(
+            decode_error(served.recv().unwrap()),
+            DecodeError::InvalidLength,
+        );
+    }
+
+    /// Forward events sent to a keyboard, returning the events a remote
+    /// keyboard yields
+    fn forward(
+        keyboard: Keyboard,
+        typed: Vec<Type>,
+        count: usize,
+    ) -> Vec<String> {
+        let remote = Keyboard::new();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let typer = keyboard.typer();
+
+        serve(Server::new(remote.typer()), move || {
+            listener.accept().unwrap().0
+        });
+        thread::spawn(move || {
+            Executor::default().spawn(async move {
+                let mut keyboard = keyboard;
+                let mut client = Client::connect(address).unwrap();
+                let _ = client.forward(&mut keyboard).await;
+            });
+        });
+        thread::spawn(move || {
+            Executor::default().spawn(async move {
+                for typed in typed {
+                    typer.send(typed).await;
+                }
+            });
+        });
+        next(remote, count).1
+    }
+
+    #[test]
+    fn forward_text() {
+        let typed = vec![
+            press(Key::A),
+            Type::Char('x'),
+            Type::Commit("한".into()),
+            Type::Preedit("a".into(), 1),
+            Type::Release(Key::A, Mods::new()),
+        ];
+
+        assert_eq!(
+            forward(Keyboard::new(), typed.clone(), 4),
+            [
+                "Press(A, Mods(0))",
+                "Char('x')",
+                "Commit(\"한\")",
+                "Release(A, Mods(0))",
+            ],
+        );
+
+        // Typed by the remote keyboard's layout instead
+        let mut keyboard = Keyboard::new();
+        keyboard.set_layout(Some(Layout::qwerty()));
+        assert_eq!(
+            forward(keyboard, typed, 2),
+            ["Press(A, Mods(0))", "Release(A, Mods(0))"],
+        );
+    }
+}
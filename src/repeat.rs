@@ -0,0 +1,223 @@
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    pin::Pin,
+    ptr,
+    sync::{
+        atomic::{AtomicPtr, AtomicU64, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+        Mutex, Once,
+    },
+    task::{Context, Poll, Waker},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{Key, Mods};
+
+/// Typematic key repeat timing
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Repeat {
+    delay: Duration,
+    interval: Duration,
+}
+
+impl Default for Repeat {
+    fn default() -> Self {
+        Self::new(Duration::from_millis(500), Duration::from_millis(33))
+    }
+}
+
+impl Repeat {
+    /// Create a new key repeat timing, from the delay before the first
+    /// repeat and the interval between each repeat after that
+    pub fn new(delay: Duration, interval: Duration) -> Self {
+        Self { delay, interval }
+    }
+
+    /// Get the delay between a key press and the first repeat
+    pub fn delay(&self) -> Duration {
+        self.delay
+    }
+
+    /// Get the interval between repeats
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+}
+
+/// Key being held down and repeated
+#[derive(Debug)]
+pub(crate) struct Held {
+    pub(crate) key: Key,
+    pub(crate) mods: Mods,
+    /// Device the key was pressed on
+    pub(crate) device: Option<u32>,
+    pub(crate) next: Instant,
+}
+
+/// Request to the timer thread, setting (or clearing) a timer's deadline
+type Request = (u64, Option<(Instant, Waker)>);
+
+/// Wakes a task at a deadline.
+///
+/// pasts executors don't provide timers, so every timer shares a single
+/// background thread, started when the first one is created.
+#[derive(Debug)]
+pub(crate) struct Timer(u64, Sender<Request>);
+
+impl Timer {
+    pub(crate) fn new() -> Self {
+        static START: Once = Once::new();
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        static SENDER: AtomicPtr<Mutex<Sender<Request>>> =
+            AtomicPtr::new(ptr::null_mut());
+
+        START.call_once(|| {
+            let (sender, receiver) = mpsc::channel();
+
+            thread::spawn(move || run(receiver));
+            let sender = Box::leak(Box::new(Mutex::new(sender)));
+            SENDER.store(sender, Ordering::Release);
+        });
+        // Safe because `SENDER` is set to a leaked (never freed) box before
+        // `call_once()` returns, and never changed after that
+        let sender = unsafe { &*SENDER.load(Ordering::Acquire) };
+        let sender = sender.lock().unwrap().clone();
+
+        Self(NEXT_ID.fetch_add(1, Ordering::Relaxed), sender)
+    }
+
+    /// Wake a task at a deadline, replacing the previous deadline (and
+    /// waking the previous task if it's a different one)
+    pub(crate) fn wake_at(&self, deadline: Instant, waker: Waker) {
+        let _ = self.1.send((self.0, Some((deadline, waker))));
+    }
+
+    /// Wait until a deadline
+    pub(crate) fn sleep_until(&self, deadline: Instant) -> Sleep<'_> {
+        Sleep(self, deadline)
+    }
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        let _ = self.1.send((self.0, None));
+    }
+}
+
+/// Future returned by [`Timer::sleep_until()`]
+#[derive(Debug)]
+pub(crate) struct Sleep<'a>(&'a Timer, Instant);
+
+impl Future for Sleep<'_> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if Instant::now() >= self.1 {
+            return Poll::Ready(());
+        }
+        self.0.wake_at(self.1, cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+/// Timer thread, waking each timer's task at its deadline
+fn run(receiver: Receiver<Request>) {
+    let mut timers = BTreeMap::<u64, (Instant, Waker)>::new();
+
+    loop {
+        let next = timers.values().map(|(deadline, _)| *deadline).min();
+        let request = match next {
+            None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            Some(deadline) => receiver.recv_timeout(
+                deadline.saturating_duration_since(Instant::now()),
+            ),
+        };
+        match request {
+            Ok((id, Some((deadline, waker)))) => {
+                let previous = timers.insert(id, (deadline, waker));
+                if let Some((_, previous)) = previous {
+                    if !previous.will_wake(&timers[&id].1) {
+                        previous.wake();
+                    }
+                }
+            }
+            Ok((id, None)) => {
+                timers.remove(&id);
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                let now = Instant::now();
+                let due = timers
+                    .iter()
+                    .filter(|(_, (deadline, _))| *deadline <= now)
+                    .map(|(id, _)| *id)
+                    .collect::<Vec<_>>();
+                for id in due {
+                    timers.remove(&id).unwrap().1.wake();
+                }
+            }
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{atomic::AtomicUsize, Arc},
+        task::Wake,
+    };
+
+    use pasts::Executor;
+
+    use super::*;
+
+    /// Waker counting its wake ups
+    struct Counter(AtomicUsize);
+
+    impl Wake for Counter {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    /// Wait up to a second for a counter to reach a count
+    fn wait_for(counter: &Counter, count: usize) {
+        let start = Instant::now();
+
+        while counter.0.load(Ordering::SeqCst) < count {
+            assert!(start.elapsed() < Duration::from_secs(1));
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn sleep() {
+        let start = Instant::now();
+
+        Executor::default().spawn(async move {
+            let (first, second) = (Timer::new(), Timer::new());
+            first.sleep_until(start + Duration::from_millis(20)).await;
+            second.sleep_until(start + Duration::from_millis(40)).await;
+        });
+        assert!(start.elapsed() >= Duration::from_millis(40));
+    }
+
+    #[test]
+    fn wake_at() {
+        let timer = Timer::new();
+        let first = Arc::new(Counter(AtomicUsize::new(0)));
+        let second = Arc::new(Counter(AtomicUsize::new(0)));
+        let later = Instant::now() + Duration::from_secs(60);
+
+        // Replacing the task wakes the previous one
+        timer.wake_at(later, first.clone().into());
+        timer.wake_at(later, second.clone().into());
+        wait_for(&first, 1);
+
+        timer.wake_at(Instant::now(), second.clone().into());
+        wait_for(&second, 1);
+        assert_eq!(first.0.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,505 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use pasts::prelude::*;
+
+use crate::{
+    repeat::Timer, Event, Key, Keyboard, Mods, Shortcut, ShortcutError, Type,
+};
+
+/// Sequence of [`Shortcut`] strokes, such as `App+X App+S`
+///
+/// # Text Format
+/// Sequences can be parsed with [`str::parse()`] and printed with
+/// [`ToString::to_string()`], as [`Shortcut`]s separated by whitespace.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Sequence(Vec<Shortcut>);
+
+impl Sequence {
+    /// Create a new sequence from its strokes
+    pub fn new(strokes: impl IntoIterator<Item = Shortcut>) -> Self {
+        Self(strokes.into_iter().collect())
+    }
+
+    /// Get the strokes
+    pub fn strokes(&self) -> &[Shortcut] {
+        &self.0
+    }
+}
+
+impl From<Shortcut> for Sequence {
+    fn from(shortcut: Shortcut) -> Self {
+        Self(vec![shortcut])
+    }
+}
+
+impl FromStr for Sequence {
+    type Err = ShortcutError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let strokes = s
+            .split_whitespace()
+            .map(str::parse)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if strokes.is_empty() {
+            return Err(ShortcutError::MissingKey);
+        }
+        Ok(Self(strokes))
+    }
+}
+
+impl fmt::Display for Sequence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, stroke) in self.0.iter().enumerate() {
+            if i != 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{}", stroke)?;
+        }
+        Ok(())
+    }
+}
+
+/// Event from a [`SequenceMap`]
+#[derive(Clone, Debug)]
+pub enum SequenceEvent<T> {
+    /// A bound sequence was typed, with its action
+    Action(T),
+    /// The start of a bound sequence was typed (to show as `App+X-`)
+    Prefix(Sequence),
+    /// The sequence typed so far was cancelled, by pressing
+    /// [`Key::Escape`], timing out, or typing a stroke that isn't bound
+    Cancel,
+}
+
+/// Node of a [`Trie`]
+#[derive(Debug)]
+struct Node<T> {
+    /// Next strokes (keyed by [`Shortcut::id()`]), and their node indices
+    next: BTreeMap<u16, usize>,
+    /// Number of bindings at or after this node
+    count: usize,
+    action: Option<T>,
+}
+
+impl<T> Node<T> {
+    fn new() -> Self {
+        Self {
+            next: BTreeMap::new(),
+            count: 0,
+            action: None,
+        }
+    }
+}
+
+/// Index of the root node of a [`Trie`] (the empty sequence)
+pub(crate) const ROOT: usize = 0;
+
+/// Trie of bound sequences, where nodes are referred to by index
+#[derive(Debug)]
+pub(crate) struct Trie<T> {
+    nodes: Vec<Node<T>>,
+}
+
+impl<T> Trie<T> {
+    pub(crate) fn new() -> Self {
+        Self {
+            nodes: vec![Node::new()],
+        }
+    }
+
+    /// Bind an action to a sequence, failing if it conflicts
+    pub(crate) fn bind(
+        &mut self,
+        sequence: Sequence,
+        action: T,
+    ) -> Result<(), ShortcutError> {
+        let conflict = || ShortcutError::SequenceConflict(sequence.clone());
+        let strokes = sequence.strokes();
+        let mut path = vec![ROOT];
+
+        if strokes.is_empty() {
+            return Err(ShortcutError::MissingKey);
+        }
+        // Check for conflicts along the existing nodes before adding any
+        for stroke in strokes {
+            let at = *path.last().unwrap();
+            if self.nodes[at].action.is_some() {
+                return Err(conflict());
+            }
+            match self.nodes[at].next.get(&stroke.id()) {
+                Some(&next) => path.push(next),
+                None => break,
+            }
+        }
+        let at = *path.last().unwrap();
+        if path.len() > strokes.len() && self.nodes[at].count != 0 {
+            return Err(conflict());
+        }
+        for stroke in &strokes[path.len() - 1..] {
+            let at = *path.last().unwrap();
+            self.nodes.push(Node::new());
+            let next = self.nodes.len() - 1;
+            self.nodes[at].next.insert(stroke.id(), next);
+            path.push(next);
+        }
+
+        let at = *path.last().unwrap();
+        for node in path {
+            self.nodes[node].count += 1;
+        }
+        self.nodes[at].action = Some(action);
+        Ok(())
+    }
+
+    /// Remove a sequence's binding, returning its action
+    pub(crate) fn unbind(&mut self, sequence: &Sequence) -> Option<T> {
+        let path = self.path(sequence)?;
+        let action = self.nodes[*path.last()?].action.take()?;
+
+        for node in path {
+            self.nodes[node].count -= 1;
+        }
+        Some(action)
+    }
+
+    /// Get the action bound to a sequence
+    pub(crate) fn get(&self, sequence: &Sequence) -> Option<&T> {
+        self.action(*self.path(sequence)?.last()?)
+    }
+
+    /// Get the node after a stroke, if any bound sequence continues with it
+    pub(crate) fn next(&self, at: usize, stroke: Shortcut) -> Option<usize> {
+        self.nodes[at]
+            .next
+            .get(&stroke.id())
+            .copied()
+            .filter(|next| self.nodes[*next].count != 0)
+    }
+
+    /// Get the action bound at a node
+    pub(crate) fn action(&self, at: usize) -> Option<&T> {
+        self.nodes[at].action.as_ref()
+    }
+
+    /// Get the node indices along a sequence, starting with the root
+    fn path(&self, sequence: &Sequence) -> Option<Vec<usize>> {
+        let mut path = vec![ROOT];
+
+        for stroke in sequence.strokes() {
+            let at = *path.last()?;
+            path.push(*self.nodes[at].next.get(&stroke.id())?);
+        }
+        Some(path)
+    }
+}
+
+/// Notifier wrapping a [`Keyboard`] that matches multi-stroke key
+/// [`Sequence`]s, yielding [`SequenceEvent`]s
+///
+/// Pressing a modifier key doesn't count as a stroke, and other events from
+/// the keyboard are discarded.  By default, the sequence typed so far is
+/// cancelled if the next stroke isn't pressed within 1 second.
+#[derive(Debug)]
+pub struct SequenceMap<T> {
+    keyboard: Keyboard,
+    trie: Trie<T>,
+    /// Current node, and the sequence typed to reach it
+    at: usize,
+    prefix: Sequence,
+    timeout: Option<Duration>,
+    deadline: Option<Instant>,
+    timer: Option<Timer>,
+}
+
+impl<T> SequenceMap<T> {
+    /// Create a new sequence map with no bindings
+    pub fn new(keyboard: Keyboard) -> Self {
+        Self {
+            keyboard,
+            trie: Trie::new(),
+            at: ROOT,
+            prefix: Sequence::default(),
+            timeout: Some(Duration::from_secs(1)),
+            deadline: None,
+            timer: None,
+        }
+    }
+
+    /// Bind an action to a sequence, failing with
+    /// [`ShortcutError::SequenceConflict`] if the sequence is already bound,
+    /// or is the start of, or starts with, a bound sequence
+    pub fn bind(
+        &mut self,
+        sequence: Sequence,
+        action: T,
+    ) -> Result<(), ShortcutError> {
+        self.trie.bind(sequence, action)
+    }
+
+    /// Remove a sequence's binding, returning its action
+    pub fn unbind(&mut self, sequence: &Sequence) -> Option<T> {
+        self.trie.unbind(sequence)
+    }
+
+    /// Get the action bound to a sequence
+    pub fn get(&self, sequence: &Sequence) -> Option<&T> {
+        self.trie.get(sequence)
+    }
+
+    /// Get the start of a bound sequence typed so far (empty if none)
+    pub fn prefix(&self) -> &Sequence {
+        &self.prefix
+    }
+
+    /// Set the time allowed between strokes before the sequence typed so far
+    /// is cancelled, or `None` to wait forever
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Get the time allowed between strokes
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// Get the wrapped keyboard
+    pub fn keyboard(&self) -> &Keyboard {
+        &self.keyboard
+    }
+
+    /// Get the wrapped keyboard mutably, to configure it
+    pub fn keyboard_mut(&mut self) -> &mut Keyboard {
+        &mut self.keyboard
+    }
+
+    /// Cancel the sequence typed so far, returning `true` if there was one
+    fn reset(&mut self) -> bool {
+        self.at = ROOT;
+        self.deadline = None;
+        !std::mem::take(&mut self.prefix.0).is_empty()
+    }
+}
+
+impl<T: Clone> SequenceMap<T> {
+    /// Match a key press against the bound sequences
+    fn stroke(
+        &mut self,
+        key: Key,
+        mods: Mods,
+        device: Option<u32>,
+    ) -> Option<SequenceEvent<T>> {
+        if modifier(key) {
+            return None;
+        }
+        if key == Key::Escape && self.at != ROOT {
+            self.reset();
+            return Some(SequenceEvent::Cancel);
+        }
+
+        let stroke = Shortcut::pressed(&self.keyboard, key, mods, device);
+        let next = match self.trie.next(self.at, stroke) {
+            Some(next) => next,
+            None => return self.reset().then(|| SequenceEvent::Cancel),
+        };
+
+        if let Some(action) = self.trie.action(next) {
+            let action = action.clone();
+            self.reset();
+            return Some(SequenceEvent::Action(action));
+        }
+        self.at = next;
+        self.prefix.0.push(stroke);
+        self.deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+        Some(SequenceEvent::Prefix(self.prefix.clone()))
+    }
+}
+
+impl<T: Clone + Unpin> Notifier for SequenceMap<T> {
+    type Event = SequenceEvent<T>;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        exec: &mut Exec<'_>,
+    ) -> Poll<Self::Event> {
+        loop {
+            let expired = self
+                .deadline
+                .map_or(false, |deadline| Instant::now() >= deadline);
+            if expired && self.reset() {
+                return Ready(SequenceEvent::Cancel);
+            }
+            match self.keyboard.poll_event(exec) {
+                Ready(Event {
+                    typed: Type::Press(key, mods),
+                    device,
+                    ..
+                }) => {
+                    if let Some(event) = self.stroke(key, mods, device) {
+                        return Ready(event);
+                    }
+                }
+                Ready(_) => {}
+                Pending => break,
+            }
+        }
+
+        if let Some(deadline) = self.deadline {
+            let timer = self.timer.get_or_insert_with(Timer::new);
+            timer.wake_at(deadline, exec.waker().clone());
+        }
+        Pending
+    }
+}
+
+/// Check if a key is a modifier key (which doesn't count as a stroke of a
+/// [`Sequence`])
+pub(crate) fn modifier(key: Key) -> bool {
+    matches!(
+        key,
+        Key::LeftShift
+            | Key::RightShift
+            | Key::LeftApp
+            | Key::RightApp
+            | Key::LeftPrg
+            | Key::RightPrg
+            | Key::Graph
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use pasts::Executor;
+
+    use super::*;
+    use crate::{Message, Sent};
+
+    fn sequence(text: &str) -> Sequence {
+        text.parse().unwrap()
+    }
+
+    /// Press and release a key on a sequence map's keyboard
+    fn tap(map: &mut SequenceMap<&str>, key: Key, mods: Mods) {
+        for typed in [Type::Press(key, mods), Type::Release(key, mods)] {
+            let sent = Sent(Message::Type(typed.into()), None, None);
+            map.keyboard.receive(sent);
+        }
+    }
+
+    /// Check if an event is a prefix of a sequence
+    fn is_prefix(event: SequenceEvent<&str>, prefix: &str) -> bool {
+        matches!(event, SequenceEvent::Prefix(p) if p == sequence(prefix))
+    }
+
+    #[test]
+    fn bind() {
+        let mut trie = Trie::new();
+
+        trie.bind(sequence("App+X App+S"), 0).unwrap();
+        trie.bind(sequence("App+X App+F"), 1).unwrap();
+        trie.bind(sequence("F1"), 2).unwrap();
+        assert_eq!(trie.get(&sequence("App+X App+S")), Some(&0));
+        assert_eq!(trie.get(&sequence("App+X App+F")), Some(&1));
+        assert_eq!(trie.get(&sequence("F1")), Some(&2));
+        assert_eq!(trie.get(&sequence("App+X")), None);
+        assert_eq!(trie.get(&sequence("App+X App+C")), None);
+        assert_eq!(
+            trie.bind(Sequence::new([]), 3),
+            Err(ShortcutError::MissingKey),
+        );
+    }
+
+    #[test]
+    fn conflicts() {
+        let mut trie = Trie::new();
+
+        trie.bind(sequence("App+X App+S"), 0).unwrap();
+        trie.bind(sequence("F1"), 1).unwrap();
+        let nodes = trie.nodes.len();
+        for conflict in ["App+X App+S", "App+X", "F1", "F1 A B"] {
+            let conflict = sequence(conflict);
+            assert_eq!(
+                trie.bind(conflict.clone(), 2),
+                Err(ShortcutError::SequenceConflict(conflict)),
+            );
+        }
+        // No nodes are left behind by failed bindings
+        assert_eq!(trie.nodes.len(), nodes);
+    }
+
+    #[test]
+    fn unbind() {
+        let mut trie = Trie::new();
+        let x = Shortcut::new(Key::X, Mods::new());
+        let s = Shortcut::new(Key::S, Mods::new());
+
+        trie.bind(sequence("X S"), 0).unwrap();
+        let after_x = trie.next(ROOT, x).unwrap();
+        assert!(trie.next(after_x, s).is_some());
+        assert_eq!(trie.unbind(&sequence("X")), None);
+        assert_eq!(trie.unbind(&sequence("X S")), Some(0));
+        assert_eq!(trie.unbind(&sequence("X S")), None);
+        assert_eq!(trie.next(ROOT, x), None);
+
+        // Unbound nodes are reused
+        let nodes = trie.nodes.len();
+        trie.bind(sequence("X"), 1).unwrap();
+        assert_eq!(trie.nodes.len(), nodes);
+        assert_eq!(trie.next(ROOT, x), Some(after_x));
+        assert_eq!(trie.action(after_x), Some(&1));
+        assert_eq!(trie.next(after_x, s), None);
+    }
+
+    #[test]
+    fn modifiers() {
+        assert!(modifier(Key::LeftShift));
+        assert!(modifier(Key::Graph));
+        assert!(!modifier(Key::Space));
+        assert!(!modifier(Key::NumLock));
+    }
+
+    #[test]
+    fn sequence_map() {
+        let mut map = SequenceMap::new(Keyboard::new());
+        let timeout = Duration::from_millis(50);
+        let mut app = Mods::new();
+
+        app.set_app(true);
+        map.bind(sequence("App+X App+S"), "save").unwrap();
+        map.set_timeout(Some(timeout));
+        Executor::default().spawn(async move {
+            tap(&mut map, Key::X, app);
+            assert!(is_prefix(map.next().await, "App+X"));
+            assert_eq!(map.prefix(), &sequence("App+X"));
+            tap(&mut map, Key::S, app);
+            assert!(matches!(map.next().await, SequenceEvent::Action("save")));
+            assert_eq!(map.prefix(), &Sequence::default());
+
+            // Cancelled by Escape
+            tap(&mut map, Key::X, app);
+            assert!(is_prefix(map.next().await, "App+X"));
+            tap(&mut map, Key::Escape, Mods::new());
+            assert!(matches!(map.next().await, SequenceEvent::Cancel));
+            assert_eq!(map.prefix(), &Sequence::default());
+
+            // Cancelled by a stroke that isn't bound
+            tap(&mut map, Key::X, app);
+            assert!(is_prefix(map.next().await, "App+X"));
+            tap(&mut map, Key::S, Mods::new());
+            assert!(matches!(map.next().await, SequenceEvent::Cancel));
+
+            // Cancelled by timing out
+            let start = Instant::now();
+            tap(&mut map, Key::X, app);
+            assert!(is_prefix(map.next().await, "App+X"));
+            assert!(matches!(map.next().await, SequenceEvent::Cancel));
+            assert!(start.elapsed() >= timeout);
+            assert_eq!(map.prefix(), &Sequence::default());
+        });
+    }
+}
@@ -0,0 +1,357 @@
+use std::{fmt, str::FromStr};
+
+use pasts::prelude::*;
+
+use crate::{Key, Keyboard, Mods, Sequence, Type};
+
+/// Modifiers that are part of a shortcut, and their names
+const MODIFIERS: [(u8, &str); 4] = [
+    (Mods::GRAPH, "Graph"),
+    (Mods::APP, "App"),
+    (Mods::PRG, "Prg"),
+    (Mods::SHIFT, "Shift"),
+];
+
+/// Error parsing or binding a [`Shortcut`]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ShortcutError {
+    /// Not the name of a modifier (`Graph`, `App`, `Prg` or `Shift`)
+    UnknownModifier(String),
+    /// The modifier was already listed
+    DuplicateModifier(String),
+    /// Not the name of a [`Key`]
+    UnknownKey(String),
+    /// Nothing after the last `+`, or an empty [`Sequence`]
+    MissingKey,
+    /// The shortcut is already bound in the [`ShortcutMap`]
+    Conflict(Shortcut),
+    /// The sequence is already bound in the
+    /// [`SequenceMap`](crate::SequenceMap), or is the start of, or starts
+    /// with, a bound sequence
+    SequenceConflict(Sequence),
+}
+
+impl fmt::Display for ShortcutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShortcutError::UnknownModifier(name) => {
+                write!(f, "unknown modifier `{}`", name)
+            }
+            ShortcutError::DuplicateModifier(name) => {
+                write!(f, "duplicate modifier `{}`", name)
+            }
+            ShortcutError::UnknownKey(name) => {
+                write!(f, "unknown key `{}`", name)
+            }
+            ShortcutError::MissingKey => write!(f, "missing key"),
+            ShortcutError::Conflict(shortcut) => {
+                write!(f, "`{}` is already bound", shortcut)
+            }
+            ShortcutError::SequenceConflict(sequence) => {
+                write!(f, "`{}` conflicts with a bound sequence", sequence)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ShortcutError {}
+
+/// Keyboard shortcut: a [`Key`] pressed while holding down an exact set of
+/// modifiers (graph, app, program and shift)
+///
+/// # Text Format
+/// Shortcuts can be parsed with [`str::parse()`] and printed with
+/// [`ToString::to_string()`], as modifiers followed by a [`Key`] name, joined
+/// with `+`, such as `App+Shift+S`.  Modifiers are printed in the order
+/// `Graph`, `App`, `Prg`, `Shift`, but may be parsed in any order.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Shortcut {
+    key: Key,
+    mods: u8,
+}
+
+impl Shortcut {
+    /// Create a new shortcut (ignoring modifiers other than graph, app,
+    /// program and shift).
+    ///
+    /// Modifier keys always have their own modifier, which is on while
+    /// they're held down, so `LeftShift` and `Shift+LeftShift` are the same
+    /// shortcut.
+    pub fn new(key: Key, mods: Mods) -> Self {
+        let mask = MODIFIERS.iter().fold(0, |mask, (bit, _)| mask | bit);
+        let own = match key {
+            Key::LeftShift | Key::RightShift => Mods::SHIFT,
+            Key::LeftApp | Key::RightApp => Mods::APP,
+            Key::LeftPrg | Key::RightPrg => Mods::PRG,
+            Key::Graph => Mods::GRAPH,
+            _ => 0,
+        };
+
+        Self {
+            key,
+            mods: mods.0 & mask | own,
+        }
+    }
+
+    /// Get the shortcut for a key pressed on a keyboard's device, leaving
+    /// out the modifiers that are only on because of ShiftLk or the app and
+    /// program locks (modifier keys held down on other devices don't count)
+    pub(crate) fn pressed(
+        keyboard: &Keyboard,
+        key: Key,
+        mut mods: Mods,
+        device: Option<u32>,
+    ) -> Self {
+        let locks = keyboard.locks();
+        let pressed = keyboard.pressed_on(device);
+        let held = |keys: [Key; 2]| keys.iter().any(|k| pressed.contains(*k));
+
+        if locks.shift() && !held([Key::LeftShift, Key::RightShift]) {
+            mods.set_shift(false);
+        }
+        if locks.app() && !held([Key::LeftApp, Key::RightApp]) {
+            mods.set_app(false);
+        }
+        if locks.prg() && !held([Key::LeftPrg, Key::RightPrg]) {
+            mods.set_prg(false);
+        }
+        Self::new(key, mods)
+    }
+
+    /// Get the key
+    pub fn key(&self) -> Key {
+        self.key
+    }
+
+    /// Get the modifiers
+    pub fn mods(&self) -> Mods {
+        Mods(self.mods)
+    }
+
+    /// Get a unique number for the shortcut, used to order it
+    pub(crate) fn id(&self) -> u16 {
+        u16::from_be_bytes([self.key as u8, self.mods])
+    }
+
+    /// Check if a key pressed with the given modifiers triggers the shortcut
+    pub fn matches(&self, key: Key, mods: Mods) -> bool {
+        *self == Self::new(key, mods)
+    }
+}
+
+impl FromStr for Shortcut {
+    type Err = ShortcutError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (modifiers, key) = s.rsplit_once('+').unwrap_or(("", s));
+        let mut mods = Mods::new();
+
+        for name in modifiers.split('+').filter(|_| !modifiers.is_empty()) {
+            let bit = MODIFIERS
+                .iter()
+                .find(|(_, n)| *n == name)
+                .map(|(bit, _)| *bit)
+                .ok_or_else(|| ShortcutError::UnknownModifier(name.into()))?;
+            if mods.get(bit) {
+                return Err(ShortcutError::DuplicateModifier(name.into()));
+            }
+            mods.set(bit, true);
+        }
+
+        if key.is_empty() {
+            return Err(ShortcutError::MissingKey);
+        }
+        let key = Key::from_name(key)
+            .ok_or_else(|| ShortcutError::UnknownKey(key.into()))?;
+
+        Ok(Self::new(key, mods))
+    }
+}
+
+impl fmt::Display for Shortcut {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (bit, name) in MODIFIERS {
+            if self.mods & bit != 0 {
+                write!(f, "{}+", name)?;
+            }
+        }
+        write!(f, "{:?}", self.key)
+    }
+}
+
+/// Notifier wrapping a [`Keyboard`] that yields the action bound to each
+/// [`Shortcut`] when it's pressed
+///
+/// Other events from the keyboard are discarded.
+#[derive(Debug)]
+pub struct ShortcutMap<T> {
+    keyboard: Keyboard,
+    bindings: Vec<(Shortcut, T)>,
+}
+
+impl<T> ShortcutMap<T> {
+    /// Create a new shortcut map with no bindings
+    pub fn new(keyboard: Keyboard) -> Self {
+        Self {
+            keyboard,
+            bindings: Vec::new(),
+        }
+    }
+
+    /// Bind an action to a shortcut, failing with
+    /// [`ShortcutError::Conflict`] if it's already bound.
+    ///
+    /// Modifiers that are only on because of ShiftLk or the app and program
+    /// locks (without their keys held down on the same device) don't stop
+    /// shortcuts from matching.
+    pub fn bind(
+        &mut self,
+        shortcut: Shortcut,
+        action: T,
+    ) -> Result<(), ShortcutError> {
+        if self.get(shortcut).is_some() {
+            return Err(ShortcutError::Conflict(shortcut));
+        }
+        self.bindings.push((shortcut, action));
+        Ok(())
+    }
+
+    /// Remove a shortcut's binding, returning its action
+    pub fn unbind(&mut self, shortcut: Shortcut) -> Option<T> {
+        let index = self.bindings.iter().position(|(s, _)| *s == shortcut)?;
+
+        Some(self.bindings.remove(index).1)
+    }
+
+    /// Get the action bound to a shortcut
+    pub fn get(&self, shortcut: Shortcut) -> Option<&T> {
+        self.bindings
+            .iter()
+            .find(|(s, _)| *s == shortcut)
+            .map(|(_, action)| action)
+    }
+
+    /// Get the wrapped keyboard
+    pub fn keyboard(&self) -> &Keyboard {
+        &self.keyboard
+    }
+
+    /// Get the wrapped keyboard mutably, to configure it
+    pub fn keyboard_mut(&mut self) -> &mut Keyboard {
+        &mut self.keyboard
+    }
+}
+
+impl<T: Clone + Unpin> Notifier for ShortcutMap<T> {
+    type Event = T;
+
+    fn poll_next(mut self: Pin<&mut Self>, exec: &mut Exec<'_>) -> Poll<T> {
+        while let Ready(event) = self.keyboard.poll_event(exec) {
+            if let Type::Press(key, mods) = event.typed {
+                let shortcut =
+                    Shortcut::pressed(&self.keyboard, key, mods, event.device);
+                if let Some(action) = self.get(shortcut) {
+                    return Ready(action.clone());
+                }
+            }
+        }
+        Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Locks;
+
+    #[test]
+    fn text_format() {
+        for (text, printed) in [
+            ("S", "S"),
+            ("Shift+App+S", "App+Shift+S"),
+            ("Graph+Prg+F1", "Graph+Prg+F1"),
+            ("LeftShift", "Shift+LeftShift"),
+            ("App+Graph", "Graph+App+Graph"),
+        ] {
+            let shortcut: Shortcut = text.parse().unwrap();
+            assert_eq!(shortcut.to_string(), printed);
+            assert_eq!(printed.parse(), Ok(shortcut));
+        }
+        assert_eq!(
+            "Ctrl+S".parse::<Shortcut>(),
+            Err(ShortcutError::UnknownModifier("Ctrl".to_string())),
+        );
+        assert_eq!(
+            "App+App+S".parse::<Shortcut>(),
+            Err(ShortcutError::DuplicateModifier("App".to_string())),
+        );
+        assert_eq!(
+            "App+s".parse::<Shortcut>(),
+            Err(ShortcutError::UnknownKey("s".to_string())),
+        );
+        assert_eq!("App+".parse::<Shortcut>(), Err(ShortcutError::MissingKey));
+    }
+
+    #[test]
+    fn conflicts() {
+        let mut map = ShortcutMap::new(Keyboard::new());
+        let shift = "Shift+LeftShift".parse().unwrap();
+
+        map.bind("App+S".parse().unwrap(), 0).unwrap();
+        map.bind("LeftShift".parse().unwrap(), 1).unwrap();
+        assert_eq!(
+            map.bind("App+S".parse().unwrap(), 2),
+            Err(ShortcutError::Conflict("App+S".parse().unwrap())),
+        );
+        assert_eq!(map.bind(shift, 2), Err(ShortcutError::Conflict(shift)));
+    }
+
+    #[test]
+    fn locks() {
+        let mut keyboard = Keyboard::new();
+        let mut locks = keyboard.locks();
+        let mut mods = Mods::new();
+
+        locks.set_shift(true);
+        locks.set_app(true);
+        keyboard.set_locks(locks);
+        mods.set_shift(true);
+        mods.set_app(true);
+        assert_eq!(
+            Shortcut::pressed(&keyboard, Key::S, mods, None),
+            Shortcut::new(Key::S, Mods::new()),
+        );
+        keyboard.set_locks(Locks::new());
+        assert_eq!(
+            Shortcut::pressed(&keyboard, Key::S, mods, None),
+            Shortcut::new(Key::S, mods),
+        );
+    }
+
+    #[test]
+    fn shortcut_map() {
+        let mut keyboard = Keyboard::new();
+        let (first, second) =
+            (keyboard.device_typer(1), keyboard.device_typer(2));
+        let mut locks = keyboard.locks();
+        let mut app = Mods::new();
+
+        locks.set_app(true);
+        keyboard.set_locks(locks);
+        app.set_app(true);
+
+        let mut map = ShortcutMap::new(keyboard);
+
+        map.bind("S".parse().unwrap(), "plain").unwrap();
+        map.bind("App+S".parse().unwrap(), "app").unwrap();
+        pasts::Executor::default().spawn(async move {
+            first.send(Type::Press(Key::LeftApp, app)).await;
+            // App is only held down on another device, so it's from AppLk
+            second.send(Type::Press(Key::S, app)).await;
+            assert_eq!(map.next().await, "plain");
+            second.send(Type::Release(Key::S, app)).await;
+            first.send(Type::Press(Key::S, app)).await;
+            assert_eq!(map.next().await, "app");
+        });
+    }
+}
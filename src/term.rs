@@ -0,0 +1,741 @@
+//! Terminal (raw TTY) input backend.
+
+use std::{
+    collections::VecDeque,
+    io::{Error, Result, Write},
+    mem::MaybeUninit,
+};
+
+use crate::{Key, Mods, Type, Typer};
+
+/// Push kitty keyboard protocol flags (disambiguate + report event types) and
+/// enable xterm modifyOtherKeys level 2
+const ENABLE: &[u8] = b"\x1b[>3u\x1b[>4;2m";
+
+/// Pop kitty keyboard protocol flags and reset xterm modifyOtherKeys
+const DISABLE: &[u8] = b"\x1b[<u\x1b[>4m";
+
+/// Time to wait for the rest of a sequence before flushing the parser, in
+/// milliseconds
+const FLUSH_TIMEOUT: i32 = 50;
+
+/// Kind of key event, as reported by the kitty keyboard protocol
+#[derive(Copy, Clone, PartialEq)]
+enum Event {
+    /// Legacy encoding, which only reports presses
+    Tap,
+    Press,
+    Repeat,
+    Release,
+}
+
+#[derive(Debug)]
+enum State {
+    Ground,
+    Escape,
+    Csi,
+    Ss3,
+    Utf8(usize),
+}
+
+/// Pure state machine decoding terminal input bytes into [`Type`] events.
+///
+/// Understands legacy xterm/VT input (control characters, `CSI` and `SS3`
+/// sequences, Alt as an `ESC` prefix), xterm modifyOtherKeys
+/// (`CSI 27 ; mods ; code ~`) and the kitty keyboard protocol
+/// (`CSI code ; mods : event u`).  Text is reported as [`Type::Char`], other
+/// keys as [`Type::Press`] followed by [`Type::Release`] (or only one of them
+/// when the terminal reports the event type).  Keys the kitty protocol
+/// reports presses of are only reported as key events, even when they type
+/// text, so the [`Keyboard`](crate::Keyboard) needs a layout to type it.
+#[derive(Debug)]
+pub struct Parser {
+    state: State,
+    buf: Vec<u8>,
+}
+
+impl Default for Parser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Parser {
+    /// Create a new parser
+    pub fn new() -> Self {
+        let state = State::Ground;
+        let buf = Vec::new();
+
+        Self { state, buf }
+    }
+
+    /// Check if the parser is partway through a sequence
+    fn is_pending(&self) -> bool {
+        !matches!(self.state, State::Ground)
+    }
+
+    /// Decode a chunk of input, calling `emit` for each complete event.
+    ///
+    /// Sequences may be split across chunks.
+    pub fn parse(&mut self, bytes: &[u8], mut emit: impl FnMut(Type)) {
+        for &byte in bytes {
+            self.byte(byte, &mut emit);
+        }
+    }
+
+    /// Finish any pending input, as no more bytes are immediately available.
+    ///
+    /// A lone `ESC` is ambiguous with the start of a sequence, so it's only
+    /// reported as [`Key::Escape`] once flushed.
+    pub fn flush(&mut self, mut emit: impl FnMut(Type)) {
+        if let State::Escape = self.state {
+            key(Key::Escape, Mods::new(), Event::Tap, &mut emit);
+        }
+        self.state = State::Ground;
+        self.buf.clear();
+    }
+
+    fn byte(&mut self, byte: u8, emit: &mut impl FnMut(Type)) {
+        match self.state {
+            State::Ground => self.ground(byte, Mods::new(), emit),
+            State::Escape => match byte {
+                b'[' => self.state = State::Csi,
+                b'O' => self.state = State::Ss3,
+                0x1b => key(Key::Escape, Mods::new(), Event::Tap, emit),
+                _ => {
+                    let mut mods = Mods::new();
+                    mods.set_prg(true);
+                    self.state = State::Ground;
+                    self.ground(byte, mods, emit);
+                }
+            },
+            State::Csi => {
+                self.buf.push(byte);
+                if (0x40..=0x7e).contains(&byte) {
+                    self.csi(emit);
+                    self.state = State::Ground;
+                    self.buf.clear();
+                } else if !(0x20..0x40).contains(&byte) {
+                    // Malformed sequence
+                    self.state = State::Ground;
+                    self.buf.clear();
+                }
+            }
+            State::Ss3 => {
+                self.state = State::Ground;
+                if let Some(k) = final_key(byte) {
+                    key(k, Mods::new(), Event::Tap, emit);
+                }
+            }
+            State::Utf8(len) => {
+                self.buf.push(byte);
+                if self.buf.len() == len {
+                    if let Ok(text) = std::str::from_utf8(&self.buf) {
+                        text.chars().for_each(|c| emit(Type::Char(c)));
+                    }
+                    self.state = State::Ground;
+                    self.buf.clear();
+                } else if byte & 0b1100_0000 != 0b1000_0000 {
+                    // Invalid continuation byte
+                    self.state = State::Ground;
+                    self.buf.clear();
+                }
+            }
+        }
+    }
+
+    fn ground(
+        &mut self,
+        byte: u8,
+        mut mods: Mods,
+        emit: &mut impl FnMut(Type),
+    ) {
+        let k = match byte {
+            0x1b => {
+                self.state = State::Escape;
+                return;
+            }
+            b'\t' => Key::Tab,
+            b'\r' | b'\n' => Key::Enter,
+            0x08 | 0x7f => Key::Erase,
+            0x00 => {
+                mods.set_app(true);
+                Key::Space
+            }
+            0x01..=0x1a => {
+                mods.set_app(true);
+                char_key(char::from(byte - 1 + b'a')).unwrap().0
+            }
+            0x1c..=0x1f => {
+                mods.set_app(true);
+                [Key::Backslash, Key::RightBracket, Key::Digit6, Key::Minus]
+                    [usize::from(byte - 0x1c)]
+            }
+            0x20..=0x7e if mods.prg() => {
+                let (k, shift) = char_key(char::from(byte)).unwrap();
+                mods.set_shift(shift);
+                k
+            }
+            0x20..=0x7e => return emit(Type::Char(char::from(byte))),
+            0xc0..=0xf7 => {
+                self.state = State::Utf8(match byte {
+                    0xc0..=0xdf => 2,
+                    0xe0..=0xef => 3,
+                    _ => 4,
+                });
+                self.buf.push(byte);
+                return;
+            }
+            _ => return,
+        };
+
+        key(k, mods, Event::Tap, emit);
+    }
+
+    fn csi(&mut self, emit: &mut impl FnMut(Type)) {
+        let (last, params) = self.buf.split_last().unwrap();
+        let params = match std::str::from_utf8(params) {
+            Ok(params) => params,
+            Err(_) => return,
+        };
+        // Private parameter sequences (such as `CSI ? flags u` replies)
+        if params.starts_with(['<', '=', '>', '?']) {
+            return;
+        }
+        let params: Vec<Vec<u32>> = params
+            .split(';')
+            .map(|p| p.split(':').map(|n| n.parse().unwrap_or(0)).collect())
+            .collect();
+        let param = |i: usize, j: usize| -> Option<u32> {
+            params.get(i).and_then(|p| p.get(j)).copied().filter(|n| *n != 0)
+        };
+        let (mods, event) = modifiers(param(1, 0), param(1, 1));
+
+        let k = match last {
+            b'u' => {
+                let code = param(0, 0).unwrap_or(0);
+                return code_key(code, mods, event, emit);
+            }
+            b'~' => match param(0, 0).unwrap_or(0) {
+                27 => {
+                    // xterm modifyOtherKeys
+                    let (mods, _) = modifiers(param(1, 0), None);
+                    let code = param(2, 0).unwrap_or(0);
+                    return code_key(code, mods, Event::Tap, emit);
+                }
+                1 | 7 => Key::PageHome,
+                2 => Key::Insert,
+                3 => Key::Remove,
+                4 | 8 => Key::PageEnd,
+                5 => Key::PageUp,
+                6 => Key::PageDown,
+                11 => Key::F1,
+                12 => Key::F2,
+                13 => Key::F3,
+                14 => Key::F4,
+                15 => Key::F5,
+                17 => Key::F6,
+                18 => Key::F7,
+                19 => Key::F8,
+                20 => Key::F9,
+                21 => Key::F10,
+                23 => Key::F11,
+                24 => Key::F12,
+                25 => Key::F13,
+                26 => Key::F14,
+                28 => Key::F15,
+                29 => Key::F16,
+                31 => Key::F17,
+                32 => Key::F18,
+                33 => Key::F19,
+                34 => Key::F20,
+                _ => return,
+            },
+            b'Z' => {
+                let mut mods = mods;
+                mods.set_shift(true);
+                return key(Key::Tab, mods, event, emit);
+            }
+            &byte => match final_key(byte) {
+                Some(k) => k,
+                None => return,
+            },
+        };
+
+        key(k, mods, event, emit);
+    }
+}
+
+/// Terminal input source, which puts standard input into raw mode for as
+/// long as it exists.
+///
+/// On creation the kitty keyboard protocol and xterm modifyOtherKeys are
+/// requested; terminals that don't support them fall back to legacy input.
+/// With the kitty protocol, text typed by keys is left to the keyboard's
+/// layout (see [`Parser`]).
+#[derive(Debug)]
+pub struct Terminal {
+    saved: libc::termios,
+    parser: Parser,
+    queue: VecDeque<Type>,
+}
+
+impl Terminal {
+    /// Put standard input into raw mode
+    pub fn new() -> Result<Self> {
+        let mut saved = MaybeUninit::uninit();
+        // Safe because `saved` is only read after successful initialization
+        let saved = unsafe {
+            if libc::tcgetattr(libc::STDIN_FILENO, saved.as_mut_ptr()) != 0 {
+                return Err(Error::last_os_error());
+            }
+            saved.assume_init()
+        };
+        let mut raw = saved;
+        // Safe because `raw` is a valid termios struct
+        unsafe {
+            libc::cfmakeraw(&mut raw);
+            if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) != 0 {
+                return Err(Error::last_os_error());
+            }
+        }
+
+        let parser = Parser::new();
+        let queue = VecDeque::new();
+        let terminal = Self {
+            saved,
+            parser,
+            queue,
+        };
+
+        // On failure, dropping the terminal restores the saved attributes
+        let mut stdout = std::io::stdout();
+        stdout.write_all(ENABLE)?;
+        stdout.flush()?;
+
+        Ok(terminal)
+    }
+
+    /// Read the next event, blocking until one is available.
+    ///
+    /// Returns `None` once standard input is closed.
+    pub fn read(&mut self) -> Result<Option<Type>> {
+        let mut buf = [0; 256];
+
+        while self.queue.is_empty() {
+            // Sequences can be split across reads, so only flush once no
+            // more input arrives
+            if self.parser.is_pending() && !readable(FLUSH_TIMEOUT)? {
+                let queue = &mut self.queue;
+                self.parser.flush(|typed| queue.push_back(typed));
+                continue;
+            }
+            // Safe because `buf` is valid for `buf.len()` bytes
+            let len = unsafe {
+                libc::read(
+                    libc::STDIN_FILENO,
+                    buf.as_mut_ptr().cast(),
+                    buf.len(),
+                )
+            };
+            let len = match len {
+                0 => return Ok(None),
+                len if len < 0 => {
+                    let error = Error::last_os_error();
+                    if error.kind() == std::io::ErrorKind::Interrupted {
+                        continue;
+                    }
+                    return Err(error);
+                }
+                len => len as usize,
+            };
+            let queue = &mut self.queue;
+            self.parser.parse(&buf[..len], |typed| queue.push_back(typed));
+        }
+
+        Ok(self.queue.pop_front())
+    }
+
+    /// Forward all events to a [`Typer`] until standard input is closed.
+    ///
+    /// Reading blocks the current thread, so this should usually be run on
+    /// an executor dedicated to the terminal.
+    pub async fn forward(mut self, typer: &Typer) -> Result<()> {
+        while let Some(typed) = self.read()? {
+            typer.send(typed).await;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let mut stdout = std::io::stdout();
+        let _ = stdout.write_all(DISABLE);
+        let _ = stdout.flush();
+        // Safe because `saved` was filled in by `tcgetattr()`
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.saved);
+        }
+    }
+}
+
+/// Wait up to `timeout` milliseconds for standard input to be readable
+fn readable(timeout: i32) -> Result<bool> {
+    let mut fd = libc::pollfd {
+        fd: libc::STDIN_FILENO,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+
+    loop {
+        // Safe because `fd` is a valid pollfd struct
+        match unsafe { libc::poll(&mut fd, 1, timeout) } {
+            0 => return Ok(false),
+            len if len < 0 => {
+                let error = Error::last_os_error();
+                if error.kind() != std::io::ErrorKind::Interrupted {
+                    return Err(error);
+                }
+            }
+            _ => return Ok(true),
+        }
+    }
+}
+
+/// Emit the events for a key
+fn key(k: Key, mods: Mods, event: Event, emit: &mut impl FnMut(Type)) {
+    match event {
+        Event::Tap => {
+            emit(Type::Press(k, mods));
+            emit(Type::Release(k, mods));
+        }
+        Event::Press => emit(Type::Press(k, mods)),
+        Event::Repeat => emit(Type::Repeat(k, mods)),
+        Event::Release => emit(Type::Release(k, mods)),
+    }
+}
+
+/// Emit the events for a unicode / kitty functional key code
+fn code_key(code: u32, mods: Mods, event: Event, emit: &mut impl FnMut(Type)) {
+    let k = match code {
+        9 => Key::Tab,
+        13 => Key::Enter,
+        27 => Key::Escape,
+        8 | 127 => Key::Erase,
+        // Caps Lock, which has no key
+        57358 => return,
+        57359 => Key::ScrollLock,
+        57360 => Key::NumLock,
+        57361 => Key::ScreenShot,
+        57362 => Key::Pause,
+        57363 => Key::Menu,
+        57376..=57387 => Key::from(Key::F13 as u8 + (code - 57376) as u8),
+        57399..=57408 => Key::from(Key::Num0 as u8 + (code - 57399) as u8),
+        57409 => Key::NumDecimalPoint,
+        57410 => Key::NumDivide,
+        57411 => Key::NumMultiply,
+        57412 => Key::NumSubtract,
+        57413 => Key::NumAdd,
+        57414 => Key::NumEnter,
+        57416 => Key::NumComma,
+        57417 => Key::NumLeft,
+        57418 => Key::NumRight,
+        57419 => Key::NumUp,
+        57420 => Key::NumDown,
+        57428..=57430 => Key::Play,
+        57432 => Key::StopEject,
+        57433 => Key::FastForward,
+        57434 => Key::Rewind,
+        57435 => Key::Next,
+        57436 => Key::Prev,
+        57438 => Key::SpeakerQuieter,
+        57439 => Key::SpeakerLouder,
+        57440 => Key::SpeakerMute,
+        57441 => Key::LeftShift,
+        57442 => Key::LeftApp,
+        57443 => Key::LeftPrg,
+        57444 => Key::LeftSys,
+        57447 => Key::RightShift,
+        57448 => Key::RightApp,
+        57449 => Key::RightPrg,
+        57450 => Key::RightSys,
+        57453 => Key::Graph,
+        code => {
+            let c = match char::from_u32(code) {
+                Some(c) => c,
+                None => return,
+            };
+            let k = char_key(c).map(|(k, _)| k);
+            // Text without command modifiers is typed as-is, unless it's a
+            // key press for the keyboard's layout to type
+            let text = match event {
+                Event::Tap => true,
+                Event::Press | Event::Repeat => k.is_none(),
+                Event::Release => false,
+            };
+            if text && !mods.app() && !mods.prg() {
+                if let Some(c) = shifted(c, mods.shift()) {
+                    return emit(Type::Char(c));
+                }
+            }
+            match k {
+                Some(k) => k,
+                None => return,
+            }
+        }
+    };
+
+    key(k, mods, event, emit);
+}
+
+/// Apply shift to a (kitty unshifted) US ASCII character
+fn shifted(c: char, shift: bool) -> Option<char> {
+    if c.is_control() {
+        return None;
+    }
+    if !shift {
+        return Some(c);
+    }
+    const UNSHIFTED: &str = "`1234567890-=\\[];',./";
+    const SHIFTED: &str = "~!@#$%^&*()_+|{}:\"<>?";
+    Some(match UNSHIFTED.find(c) {
+        Some(i) => SHIFTED[i..].chars().next().unwrap(),
+        None => c.to_ascii_uppercase(),
+    })
+}
+
+/// Parse the modifier parameter (`1 + bitmask`) and event type sub-parameter
+fn modifiers(mods: Option<u32>, event: Option<u32>) -> (Mods, Event) {
+    let bits = mods.unwrap_or(1).saturating_sub(1);
+    let mut mods = Mods::new();
+
+    mods.set_shift(bits & 0b0001 != 0);
+    mods.set_prg(bits & 0b0010 != 0);
+    mods.set_app(bits & 0b0100 != 0);
+
+    let event = match event {
+        None => Event::Tap,
+        Some(2) => Event::Repeat,
+        Some(3) => Event::Release,
+        Some(_) => Event::Press,
+    };
+
+    (mods, event)
+}
+
+/// Key for the final byte of a `CSI` / `SS3` sequence
+fn final_key(byte: u8) -> Option<Key> {
+    Some(match byte {
+        b'A' => Key::Up,
+        b'B' => Key::Down,
+        b'C' => Key::Right,
+        b'D' => Key::Left,
+        b'H' => Key::PageHome,
+        b'F' => Key::PageEnd,
+        b'E' => Key::Num5,
+        b'M' => Key::NumEnter,
+        b'P' => Key::F1,
+        b'Q' => Key::F2,
+        b'R' => Key::F3,
+        b'S' => Key::F4,
+        _ => return None,
+    })
+}
+
+/// Physical key (on a US QWERTY layout) typing an ASCII character, and
+/// whether shift is needed
+fn char_key(c: char) -> Option<(Key, bool)> {
+    use Key::*;
+
+    const KEYS: [(Key, char, char); 47] = [
+        (Grave, '`', '~'),
+        (Digit1, '1', '!'),
+        (Digit2, '2', '@'),
+        (Digit3, '3', '#'),
+        (Digit4, '4', '$'),
+        (Digit5, '5', '%'),
+        (Digit6, '6', '^'),
+        (Digit7, '7', '&'),
+        (Digit8, '8', '*'),
+        (Digit9, '9', '('),
+        (Digit0, '0', ')'),
+        (Minus, '-', '_'),
+        (Equals, '=', '+'),
+        (Backslash, '\\', '|'),
+        (Q, 'q', 'Q'),
+        (W, 'w', 'W'),
+        (E, 'e', 'E'),
+        (R, 'r', 'R'),
+        (T, 't', 'T'),
+        (Y, 'y', 'Y'),
+        (U, 'u', 'U'),
+        (I, 'i', 'I'),
+        (O, 'o', 'O'),
+        (P, 'p', 'P'),
+        (LeftBracket, '[', '{'),
+        (RightBracket, ']', '}'),
+        (A, 'a', 'A'),
+        (S, 's', 'S'),
+        (D, 'd', 'D'),
+        (F, 'f', 'F'),
+        (G, 'g', 'G'),
+        (H, 'h', 'H'),
+        (J, 'j', 'J'),
+        (K, 'k', 'K'),
+        (L, 'l', 'L'),
+        (Semicolon, ';', ':'),
+        (Apostrophe, '\'', '"'),
+        (Z, 'z', 'Z'),
+        (X, 'x', 'X'),
+        (C, 'c', 'C'),
+        (V, 'v', 'V'),
+        (B, 'b', 'B'),
+        (N, 'n', 'N'),
+        (M, 'm', 'M'),
+        (Comma, ',', '<'),
+        (Period, '.', '>'),
+        (Slash, '/', '?'),
+    ];
+
+    if c == ' ' {
+        return Some((Space, false));
+    }
+
+    KEYS.iter().find_map(|&(k, base, shift)| {
+        if c == base {
+            Some((k, false))
+        } else if c == shift {
+            Some((k, true))
+        } else {
+            None
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parse chunks of input and flush, collecting the events
+    fn parse(chunks: &[&[u8]]) -> Vec<String> {
+        let mut parser = Parser::new();
+        let mut events = Vec::new();
+
+        for chunk in chunks {
+            parser.parse(chunk, |typed| events.push(format!("{:?}", typed)));
+        }
+        parser.flush(|typed| events.push(format!("{:?}", typed)));
+        events
+    }
+
+    #[test]
+    fn text() {
+        assert_eq!(
+            parse(&[b"aZ \xc3\xa9"]),
+            ["Char('a')", "Char('Z')", "Char(' ')", "Char('é')"],
+        );
+    }
+
+    #[test]
+    fn control() {
+        assert_eq!(
+            parse(&[b"\x13\r\x7f"]),
+            [
+                "Press(S, Mods(32))",
+                "Release(S, Mods(32))",
+                "Press(Enter, Mods(0))",
+                "Release(Enter, Mods(0))",
+                "Press(Erase, Mods(0))",
+                "Release(Erase, Mods(0))",
+            ],
+        );
+    }
+
+    #[test]
+    fn escape() {
+        assert_eq!(
+            parse(&[b"\x1b"]),
+            ["Press(Escape, Mods(0))", "Release(Escape, Mods(0))"],
+        );
+        assert_eq!(
+            parse(&[b"\x1bX"]),
+            ["Press(X, Mods(24))", "Release(X, Mods(24))"],
+        );
+    }
+
+    #[test]
+    fn legacy() {
+        assert_eq!(
+            parse(&[b"\x1b[A\x1bOP\x1b[3~\x1b[1;5D\x1b[Z"]),
+            [
+                "Press(Up, Mods(0))",
+                "Release(Up, Mods(0))",
+                "Press(F1, Mods(0))",
+                "Release(F1, Mods(0))",
+                "Press(Remove, Mods(0))",
+                "Release(Remove, Mods(0))",
+                "Press(Left, Mods(32))",
+                "Release(Left, Mods(32))",
+                "Press(Tab, Mods(8))",
+                "Release(Tab, Mods(8))",
+            ],
+        );
+    }
+
+    #[test]
+    fn modify_other_keys() {
+        assert_eq!(
+            parse(&[b"\x1b[27;5;115~"]),
+            ["Press(S, Mods(32))", "Release(S, Mods(32))"],
+        );
+    }
+
+    #[test]
+    fn kitty() {
+        assert_eq!(
+            parse(&[b"\x1b[115;5:1u\x1b[115;5:2u\x1b[115;5:3u"]),
+            [
+                "Press(S, Mods(32))",
+                "Repeat(S, Mods(32))",
+                "Release(S, Mods(32))",
+            ],
+        );
+        assert_eq!(
+            parse(&[b"\x1b[57441;2:1u\x1b[97;2:1u\x1b[97;2:3u"]),
+            [
+                "Press(LeftShift, Mods(8))",
+                "Press(A, Mods(8))",
+                "Release(A, Mods(8))",
+            ],
+        );
+    }
+
+    #[test]
+    fn kitty_text() {
+        // Text with no key is typed as-is
+        assert_eq!(
+            parse(&[b"\x1b[1081;1:1u\x1b[1081;1:2u\x1b[1081;1:3u"]),
+            ["Char('й')", "Char('й')"],
+        );
+    }
+
+    #[test]
+    fn caps_lock() {
+        assert!(parse(&[b"\x1b[57358;1:1u\x1b[57358;1:3u"]).is_empty());
+    }
+
+    #[test]
+    fn split() {
+        assert_eq!(
+            parse(&[b"\x1b", b"[1;5", b"D"]),
+            ["Press(Left, Mods(32))", "Release(Left, Mods(32))"],
+        );
+        assert_eq!(parse(&[b"\xc3", b"\xa9"]), ["Char('é')"]);
+    }
+
+    #[test]
+    fn replies() {
+        assert!(parse(&[b"\x1b[?3u"]).is_empty());
+    }
+}
@@ -0,0 +1,518 @@
+//! Binary wire format for [`Type`]s.
+
+use std::io::{Error, ErrorKind, Read, Result, Write};
+
+use crate::{DecodeError, Type, TypeInternal, Typer};
+
+/// Magic bytes at the start of a stream (or [`Recording`](crate::Recording))
+pub(crate) const MAGIC: &[u8; 4] = b"KBRD";
+/// Version of the wire format
+pub(crate) const VERSION: u8 = 1;
+/// Maximum length of the text of an event, in bytes
+const MAX_TEXT: usize = 0x10000;
+
+impl Type {
+    /// Encode in the wire format.
+    ///
+    /// Each event is a 4-byte header.  Character events are the character
+    /// in UTF-8, padded with zero bytes.  Key events are `FF`, the key code,
+    /// the state (`00` released, `01` pressed, `02` repeated) and the
+    /// modifiers, or `FF 00 03` followed by the locks.  Text events are `FE`
+    /// and the kind (`00` commit, `01` preedit with the cursor as the
+    /// little-endian last two bytes, `02` cancel), followed by the length of
+    /// the text as an unsigned LEB128 integer, and the text in UTF-8.  Text
+    /// longer than 64 KiB fails to decode, so it fails to encode with
+    /// [`DecodeError::InvalidLength`].
+    ///
+    /// This is version 1 of the format, which won't change without a new
+    /// version number in the [`Encoder`] stream header.
+    pub fn to_bytes(&self) -> std::result::Result<Vec<u8>, DecodeError> {
+        let mut bytes = Vec::new();
+
+        encode(self, &mut bytes)?;
+        Ok(bytes)
+    }
+
+    /// Decode an event from the wire format (see [`Type::to_bytes()`]),
+    /// which must contain exactly one event.  Decoding is strict, so events
+    /// that could be repaired fail to decode (see [`DecodeError`]).
+    pub fn from_bytes(bytes: &[u8]) -> std::result::Result<Self, DecodeError> {
+        let mut rest = bytes;
+        let typed = read(&mut rest, true)
+            .map_err(decode_error)?
+            .ok_or(DecodeError::Truncated)?;
+
+        if !rest.is_empty() {
+            return Err(DecodeError::ExtraBytes(rest.len()));
+        }
+        Ok(typed)
+    }
+}
+
+/// Writer of [`Type`]s in the wire format (see [`Type::to_bytes()`]), after
+/// a stream header of the `KBRD` magic bytes and the version byte
+#[derive(Debug)]
+pub struct Encoder<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> Encoder<W> {
+    /// Create a new encoder, writing the stream header
+    pub fn new(mut writer: W) -> Result<Self> {
+        let mut header = Vec::new();
+
+        write_header(&mut header);
+        writer.write_all(&header)?;
+        Ok(Self { writer })
+    }
+
+    /// Write an event, failing with [`ErrorKind::InvalidInput`] if its text
+    /// is too long to decode
+    pub fn write(&mut self, typed: &Type) -> Result<()> {
+        self.writer.write_all(&checked(typed)?)
+    }
+
+    /// Flush the writer
+    pub fn flush(&mut self) -> Result<()> {
+        self.writer.flush()
+    }
+
+    /// Get the writer
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    /// Get the writer back
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Reader of [`Type`]s in the wire format, written by an [`Encoder`]
+///
+/// Decoding errors (including streams ending in the middle of an event) are
+/// returned as [`ErrorKind::InvalidData`] I/O errors wrapping a
+/// [`DecodeError`].
+#[derive(Debug)]
+pub struct Decoder<R: Read> {
+    reader: R,
+    strict: bool,
+}
+
+impl<R: Read> Decoder<R> {
+    /// Create a new decoder, reading and checking the stream header
+    pub fn new(mut reader: R) -> Result<Self> {
+        read_header(&mut reader)?;
+
+        Ok(Self {
+            reader,
+            strict: true,
+        })
+    }
+
+    /// Set whether events are decoded strictly (`true` by default).
+    ///
+    /// When not strict, events that can be repaired are, instead of failing
+    /// to decode (see [`DecodeError`]).
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    /// Check if events are decoded strictly
+    pub fn strict(&self) -> bool {
+        self.strict
+    }
+
+    /// Read the next event, blocking until one is available.  Returns `None`
+    /// once the reader reaches the end of the stream.
+    pub fn read(&mut self) -> Result<Option<Type>> {
+        read(&mut self.reader, self.strict)
+    }
+
+    /// Forward all events to a [`Typer`] until the end of the stream.
+    ///
+    /// Reading blocks the current thread, so this should usually be run on
+    /// an executor dedicated to the stream.
+    pub async fn forward(mut self, typer: &Typer) -> Result<()> {
+        while let Some(typed) = self.read()? {
+            typer.send(typed).await;
+        }
+        Ok(())
+    }
+
+    /// Get the reader
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    /// Get the reader back
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+/// Read an event in the wire format, returning `None` at the end of the
+/// stream
+pub(crate) fn read<R: Read>(
+    reader: &mut R,
+    strict: bool,
+) -> Result<Option<Type>> {
+    let mut header = [0; 4];
+    let mut text = Vec::new();
+
+    match fill(reader, &mut header)? {
+        0 => return Ok(None),
+        4 => {}
+        _ => return Err(DecodeError::Truncated.into()),
+    }
+    if header[0] == TypeInternal::TEXT {
+        let len = usize::try_from(read_varint(reader)?)
+            .ok()
+            .filter(|len| *len <= MAX_TEXT)
+            .ok_or(DecodeError::InvalidLength)?;
+
+        text = vec![0; len];
+        fill_exact(reader, &mut text)?;
+    }
+    Ok(Some(decode(header, text, strict)?))
+}
+
+/// Read and check the stream header
+pub(crate) fn read_header<R: Read>(reader: &mut R) -> Result<()> {
+    let mut header = [0; 5];
+    let len = fill(reader, &mut header)?;
+    let magic = len.min(MAGIC.len());
+
+    if header[..magic] != MAGIC[..magic] {
+        return Err(DecodeError::InvalidMagic.into());
+    }
+    if len < header.len() {
+        return Err(DecodeError::Truncated.into());
+    }
+    if header[4] != VERSION {
+        return Err(DecodeError::UnsupportedVersion(header[4]).into());
+    }
+    Ok(())
+}
+
+/// Append the stream header
+pub(crate) fn write_header(bytes: &mut Vec<u8>) {
+    bytes.extend_from_slice(MAGIC);
+    bytes.push(VERSION);
+}
+
+/// Append an event in the wire format, failing with
+/// [`DecodeError::InvalidLength`] (without appending anything) if its text
+/// is too long to decode
+pub(crate) fn encode(
+    typed: &Type,
+    bytes: &mut Vec<u8>,
+) -> std::result::Result<(), DecodeError> {
+    let TypeInternal(header, text) = typed.clone().into();
+
+    if text.len() > MAX_TEXT {
+        return Err(DecodeError::InvalidLength);
+    }
+    bytes.extend_from_slice(&header);
+    if header[0] == TypeInternal::TEXT {
+        write_varint(bytes, text.len() as u64);
+        bytes.extend_from_slice(text.as_bytes());
+    }
+    Ok(())
+}
+
+/// Encode an event in the wire format, failing with
+/// [`ErrorKind::InvalidInput`] if its text is too long to decode
+pub(crate) fn checked(typed: &Type) -> Result<Vec<u8>> {
+    typed
+        .to_bytes()
+        .map_err(|e| Error::new(ErrorKind::InvalidInput, e))
+}
+
+/// Write an unsigned LEB128 integer
+pub(crate) fn write_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+
+        value >>= 7;
+        if value == 0 {
+            bytes.push(byte);
+            return;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+/// Read an unsigned LEB128 integer, failing with
+/// [`DecodeError::InvalidLength`] if it doesn't fit in 64 bits
+pub(crate) fn read_varint<R: Read>(reader: &mut R) -> Result<u64> {
+    let mut value = 0u64;
+
+    for shift in (0..64).step_by(7) {
+        let mut byte = [0];
+        fill_exact(reader, &mut byte)?;
+        value |= u64::from(byte[0] & 0x7F) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(DecodeError::InvalidLength.into())
+}
+
+/// Get the decoding error wrapped by an error from reading a byte slice
+/// (which can't fail otherwise)
+pub(crate) fn decode_error(error: Error) -> DecodeError {
+    error
+        .into_inner()
+        .and_then(|error| error.downcast().ok())
+        .map_or(DecodeError::Truncated, |error| *error)
+}
+
+/// Read into a buffer until it's full or the reader ends, returning the
+/// number of bytes read
+fn fill<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<usize> {
+    let mut len = 0;
+
+    while len < buf.len() {
+        match reader.read(&mut buf[len..]) {
+            Ok(0) => break,
+            Ok(read) => len += read,
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(len)
+}
+
+/// Read into a buffer until it's full, failing with
+/// [`DecodeError::Truncated`] if the reader ends first
+fn fill_exact<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<()> {
+    if fill(reader, buf)? < buf.len() {
+        return Err(DecodeError::Truncated.into());
+    }
+    Ok(())
+}
+
+/// Decode an event from its header and text
+fn decode(
+    header: [u8; 4],
+    text: Vec<u8>,
+    strict: bool,
+) -> std::result::Result<Type, DecodeError> {
+    let text = String::from_utf8(text).map_err(|_| DecodeError::InvalidText)?;
+
+    TypeInternal(header, text).decode(strict)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Key, Locks, Mods};
+
+    /// Format a decoded event or decoding error
+    fn show(
+        decoded: std::result::Result<Type, impl std::fmt::Debug>,
+    ) -> String {
+        match decoded {
+            Ok(typed) => format!("{:?}", typed),
+            Err(error) => format!("{:?}", error),
+        }
+    }
+
+    /// Decode an event, strictly or not, through a [`Decoder`]
+    fn read(bytes: &[u8], strict: bool) -> String {
+        let stream = [&MAGIC[..], &[VERSION], bytes].concat();
+        let mut decoder = Decoder::new(&stream[..]).unwrap();
+
+        decoder.set_strict(strict);
+        show(decoder.read().map(Option::unwrap).map_err(|error| {
+            error
+                .into_inner()
+                .unwrap()
+                .downcast::<DecodeError>()
+                .unwrap()
+        }))
+    }
+
+    #[test]
+    fn invalid() {
+        for (bytes, strict, lenient) in [
+            (
+                &[0xFF, 0x1C, 0x04, 0x00][..],
+                "InvalidKeyState(4)",
+                "InvalidKeyState(4)",
+            ),
+            (
+                &[0xFE, 0x03, 0x00, 0x00, 0x00],
+                "InvalidTextKind(3)",
+                "InvalidTextKind(3)",
+            ),
+            (
+                &[0x80, 0x61, 0x00, 0x00],
+                "InvalidUtf8([128, 97, 0, 0])",
+                "InvalidUtf8([128, 97, 0, 0])",
+            ),
+            (
+                &[0x61, 0x62, 0x00, 0x00],
+                "TrailingBytes([97, 98, 0, 0])",
+                "Char('a')",
+            ),
+            (
+                &[0xC3, 0xA9, 0x80, 0x00],
+                "TrailingBytes([195, 169, 128, 0])",
+                "Char('é')",
+            ),
+            (
+                &[0xFF, 0x00, 0x03, 0xFF],
+                "InvalidLocks(255)",
+                "Lock(Locks(63))",
+            ),
+            (
+                &[0xFE, 0x01, 0x01, 0x00, 0x02, 0xC3, 0xA9],
+                "InvalidCursor(1)",
+                "Preedit(\"é\", 0)",
+            ),
+            (
+                &[0xFE, 0x01, 0x09, 0x00, 0x02, 0xC3, 0xA9],
+                "InvalidCursor(9)",
+                "Preedit(\"é\", 2)",
+            ),
+            (
+                &[0xFE, 0x00, 0x00, 0x00, 0x01, 0xFF],
+                "InvalidText",
+                "InvalidText",
+            ),
+            (
+                &[0xFE, 0x00, 0x01, 0x00, 0x01, 0x61],
+                "InvalidHeader([254, 0, 1, 0])",
+                "Commit(\"a\")",
+            ),
+            (
+                &[0xFE, 0x00, 0x00, 0x01, 0x01, 0x61],
+                "InvalidHeader([254, 0, 0, 1])",
+                "Commit(\"a\")",
+            ),
+            (
+                &[0xFE, 0x02, 0x00, 0x80, 0x00],
+                "InvalidHeader([254, 2, 0, 128])",
+                "Cancel",
+            ),
+            (
+                &[0xFE, 0x02, 0x00, 0x00, 0x01, 0x61],
+                "UnexpectedText(1)",
+                "Cancel",
+            ),
+            (
+                &[0xFF, 0x1C, 0x03, 0x10],
+                "InvalidHeader([255, 28, 3, 16])",
+                "Lock(Locks(16))",
+            ),
+        ] {
+            assert_eq!(read(bytes, true), strict);
+            assert_eq!(read(bytes, false), lenient);
+            assert_eq!(show(Type::from_bytes(bytes)), strict);
+        }
+    }
+
+    /// Events of every kind
+    fn events() -> Vec<Type> {
+        vec![
+            Type::Char('a'),
+            Type::Char('한'),
+            Type::Press(Key::LeftShift, Mods(0x08)),
+            Type::Release(Key::A, Mods(0x87)),
+            Type::Repeat(Key::A, Mods(0x08)),
+            Type::Lock(Locks(0x21)),
+            Type::Preedit("한국".into(), 3),
+            Type::Commit("x".repeat(200)),
+            Type::Cancel,
+        ]
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut encoder = Encoder::new(Vec::new()).unwrap();
+
+        for typed in events() {
+            let bytes = typed.to_bytes().unwrap();
+            assert_eq!(show(Type::from_bytes(&bytes)), format!("{:?}", typed));
+            encoder.write(&typed).unwrap();
+        }
+
+        let stream = encoder.into_inner();
+        let mut decoder = Decoder::new(&stream[..]).unwrap();
+        for typed in events() {
+            let decoded = decoder.read().unwrap().unwrap();
+            assert_eq!(format!("{:?}", decoded), format!("{:?}", typed));
+        }
+        assert!(decoder.read().unwrap().is_none());
+    }
+
+    #[test]
+    fn truncated() {
+        assert_eq!(show(Type::from_bytes(&[])), "Truncated");
+        for typed in events() {
+            let bytes = typed.to_bytes().unwrap();
+
+            for len in 1..bytes.len() {
+                assert_eq!(read(&bytes[..len], true), "Truncated", "{}", len);
+                assert_eq!(
+                    show(Type::from_bytes(&bytes[..len])),
+                    "Truncated",
+                    "{}",
+                    len,
+                );
+            }
+            let mut extra = bytes.clone();
+            extra.push(0);
+            assert_eq!(show(Type::from_bytes(&extra)), "ExtraBytes(1)");
+        }
+    }
+
+    #[test]
+    fn header() {
+        let header = |bytes: &[u8]| {
+            show(Decoder::new(bytes).map(|_| Type::Cancel).map_err(|e| {
+                e.into_inner().unwrap().downcast::<DecodeError>().unwrap()
+            }))
+        };
+
+        assert_eq!(header(b"KBRD\x01"), "Cancel");
+        assert_eq!(header(b""), "Truncated");
+        assert_eq!(header(b"KB"), "Truncated");
+        assert_eq!(header(b"KBEV\x01"), "InvalidMagic");
+        assert_eq!(header(b"KX"), "InvalidMagic");
+        assert_eq!(header(b"KBRD\x02"), "UnsupportedVersion(2)");
+        // A partial event header isn't the end of the stream
+        assert_eq!(read(b"\xFF\x10", true), "Truncated");
+    }
+
+    #[test]
+    fn text_length() {
+        let long = |len| Type::Commit("x".repeat(len));
+        let mut encoder = Encoder::new(Vec::new()).unwrap();
+
+        let bytes = long(MAX_TEXT).to_bytes().unwrap();
+        assert_eq!(read(&bytes, true).len(), MAX_TEXT + 10);
+        assert_eq!(
+            long(MAX_TEXT + 1).to_bytes().unwrap_err(),
+            DecodeError::InvalidLength,
+        );
+        // Written by another encoder
+        let mut bytes = vec![0xFE, 0x00, 0x00, 0x00];
+        write_varint(&mut bytes, MAX_TEXT as u64 + 1);
+        bytes.resize(bytes.len() + MAX_TEXT + 1, b'x');
+        assert_eq!(read(&bytes, true), "InvalidLength");
+        assert_eq!(
+            encoder.write(&long(MAX_TEXT + 1)).unwrap_err().kind(),
+            ErrorKind::InvalidInput,
+        );
+        encoder.write(&long(MAX_TEXT)).unwrap();
+
+        // Lengths that don't fit in 64 bits
+        let mut bytes = vec![0xFE, 0x00, 0x00, 0x00];
+        bytes.extend_from_slice(&[0xFF; 10]);
+        bytes.push(0x01);
+        assert_eq!(read(&bytes, true), "InvalidLength");
+    }
+}